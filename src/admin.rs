@@ -1,5 +1,13 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
+use crate::body::encode_body;
+use crate::client_pool::ClientPool;
 use crate::config::Config;
+use crate::delivery_log::Outcome;
+use crate::egress::EgressPolicy;
+use crate::script::CompiledScript;
+use crate::target::TargetTemplates;
+use crate::target_auth::TokenCache;
+use crate::wasm::WasmTransform;
 use std::path::PathBuf;
 use tracing::info;
 
@@ -27,9 +35,15 @@ pub enum AdminCommands {
         /// Endpoint to test
         #[arg(short, long)]
         endpoint: String,
-        /// JSON payload to test with
+        /// Payload to test with (JSON unless --content-type says otherwise)
         #[arg(short, long)]
         payload: String,
+        /// Content-Type the payload is decoded as, e.g. application/x-www-form-urlencoded
+        #[arg(long)]
+        content_type: Option<String>,
+        /// Send the rendered request to the target, subject to the egress policy
+        #[arg(long)]
+        send: bool,
     },
     /// List all registered endpoints
     ListEndpoints {
@@ -37,6 +51,129 @@ pub enum AdminCommands {
         #[arg(short, long, default_value = "config.yml")]
         config: PathBuf,
     },
+    /// Follow processed webhooks live through the admin API
+    Tail {
+        #[command(flatten)]
+        remote: Remote,
+        /// Only show webhooks received on this endpoint
+        #[arg(short, long)]
+        endpoint: Option<String>,
+    },
+    /// Manage the registers of a running server through the admin API
+    Registers {
+        #[command(flatten)]
+        remote: Remote,
+        #[command(subcommand)]
+        action: RegisterAction,
+    },
+    /// Hold back webhooks for a register or target of a running server
+    Pause {
+        #[command(flatten)]
+        remote: Remote,
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Send what a register or target held back, and stop holding
+    Resume {
+        #[command(flatten)]
+        remote: Remote,
+        #[command(flatten)]
+        scope: ScopeArgs,
+    },
+    /// Show what a running server has paused and buffered
+    Paused {
+        #[command(flatten)]
+        remote: Remote,
+    },
+    /// Re-render stored inbound events with the current configuration and
+    /// print them, or send them to their targets with --send
+    Replay {
+        /// Path to configuration file
+        #[arg(short, long, default_value = "config.yml")]
+        config: PathBuf,
+        /// JSONL files of delivery records or events; defaults to the
+        /// configured delivery log
+        #[arg(long = "from")]
+        files: Vec<PathBuf>,
+        /// Only replay events received on this endpoint
+        #[arg(short, long)]
+        endpoint: Option<String>,
+        /// Only replay events received since this RFC 3339 time or this long ago, e.g. 1h
+        #[arg(long)]
+        since: Option<String>,
+        /// Only replay events received until this RFC 3339 time or this long ago
+        #[arg(long)]
+        until: Option<String>,
+        /// Send the rendered requests to their targets, subject to the egress policy
+        #[arg(long)]
+        send: bool,
+        /// Requests sent per second
+        #[arg(long, default_value_t = 5.0)]
+        rate: f64,
+    },
+}
+
+/// Where the admin API of a running server is.
+#[derive(Args)]
+pub struct Remote {
+    /// Base URL of the running server's admin API
+    #[arg(long, env = "HERMES_ADMIN_URL", default_value = "http://localhost:3000")]
+    url: String,
+    /// Bearer token for the admin API
+    #[arg(long, env = "HERMES_ADMIN_TOKEN")]
+    token: Option<String>,
+}
+
+/// A register or a target to pause or resume.
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+pub struct ScopeArgs {
+    /// Endpoint of the register
+    #[arg(long)]
+    register: Option<String>,
+    /// Target URL; every register sending to its origin is affected
+    #[arg(long)]
+    target: Option<String>,
+}
+
+impl ScopeArgs {
+    fn scope(self) -> crate::pause::Scope {
+        match (self.register, self.target) {
+            (Some(endpoint), _) => crate::pause::Scope::Register(endpoint),
+            (None, target) => crate::pause::Scope::Target(target.unwrap_or_default()),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum RegisterAction {
+    /// List the registers being served
+    List,
+    /// Show a register as written
+    Get { endpoint: String },
+    /// Add a register from a YAML or JSON file
+    Create {
+        #[arg(short, long)]
+        file: PathBuf,
+        /// Only validate the register
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Replace a register with one from a YAML or JSON file
+    Update {
+        endpoint: String,
+        #[arg(short, long)]
+        file: PathBuf,
+        /// Only validate the register
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove a register
+    Delete { endpoint: String },
+    /// Serve a disabled register again
+    Enable { endpoint: String },
+    /// Answer 404 on a register's endpoint until it is enabled
+    Disable { endpoint: String },
 }
 
 pub async fn run_admin_command(cmd: AdminCommands) -> Result<(), Box<dyn std::error::Error>> {
@@ -45,12 +182,39 @@ pub async fn run_admin_command(cmd: AdminCommands) -> Result<(), Box<dyn std::er
             validate_config(&config).await?;
             println!("✅ Configuration is valid");
         }
-        AdminCommands::TestTemplate { config, endpoint, payload } => {
-            test_template(&config, &endpoint, &payload).await?;
+        AdminCommands::TestTemplate { config, endpoint, payload, content_type, send } => {
+            test_template(&config, &endpoint, &payload, content_type.as_deref(), send).await?;
         }
         AdminCommands::ListEndpoints { config } => {
             list_endpoints(&config).await?;
         }
+        AdminCommands::Tail { remote, endpoint } => {
+            tail(&remote, endpoint.as_deref()).await?;
+        }
+        AdminCommands::Registers { remote, action } => {
+            registers(&remote, action).await?;
+        }
+        AdminCommands::Pause { remote, scope } => {
+            let body = send_pause(&remote, "/admin/pause", scope.scope()).await?;
+            println!("⏸️  Paused {}", describe_scope(&body["paused"]));
+        }
+        AdminCommands::Resume { remote, scope } => {
+            let body = send_pause(&remote, "/admin/resume", scope.scope()).await?;
+            println!("▶️  Resumed {}", describe_scope(&body["resumed"]));
+            println!("   {} request(s) still buffered", body["status"]["buffered"]);
+        }
+        AdminCommands::Paused { remote } => {
+            paused(&remote).await?;
+        }
+        AdminCommands::Replay { config, files, endpoint, since, until, send, rate } => {
+            let now = chrono::Utc::now();
+            let filter = crate::replay::ReplayFilter {
+                endpoint,
+                since: since.map(|s| crate::replay::parse_time(&s, now)).transpose()?,
+                until: until.map(|s| crate::replay::parse_time(&s, now)).transpose()?,
+            };
+            replay(&config, files, &filter, send, rate).await?;
+        }
     }
     Ok(())
 }
@@ -59,9 +223,42 @@ async fn validate_config(config_path: &PathBuf) -> Result<(), Box<dyn std::error
     let config = Config::load(config_path).await?;
     
     info!("Validating {} webhook registers", config.registers.len());
+
+    // Validate source restrictions, including ranges files
+    crate::sources::SourcePolicy::new(&config.settings, &config.registers)?;
+
+    // Validate the egress policy
+    let egress = EgressPolicy::compile(&config.settings.egress)?;
+
+    if let Some(header) = &config.settings.request_id_header {
+        crate::request_id::forward_header(header)?;
+    }
+
+    crate::redaction::Redactor::compile(&config.settings.redaction)
+        .map_err(|e| format!("settings.redaction: {}", e))?;
+
+    crate::debug_bin::canned_response(&config.settings.debug.response)
+        .map_err(|e| format!("settings.debug: {}", e))?;
+
+    if let Some(admin) = &config.settings.admin {
+        crate::inbound_auth::InboundAuthenticator::compile(&admin.auth)
+            .map_err(|e| format!("settings.admin: {}", e))?;
+        if let Some(address) = &admin.bind_address {
+            address
+                .parse::<std::net::SocketAddr>()
+                .map_err(|e| format!("settings.admin.bind_address: {}", e))?;
+        }
+    }
     
+    crate::pause::PauseControl::new(&config.settings.pause).map_err(|e| format!("settings.pause: {}", e))?;
+
     // Validate each register
+    let mut endpoints = std::collections::HashSet::new();
     for (i, register) in config.registers.iter().enumerate() {
+        if !endpoints.insert(register.endpoint.as_str()) {
+            return Err(format!("Register {}: endpoint {} is registered more than once", i, register.endpoint).into());
+        }
+
         // Check endpoint format
         if !register.endpoint.starts_with('/') {
             return Err(format!("Register {}: endpoint must start with '/'", i).into());
@@ -78,10 +275,52 @@ async fn validate_config(config_path: &PathBuf) -> Result<(), Box<dyn std::error
             return Err(format!("Register {}: target URL cannot be empty", i).into());
         }
         
+        // A register needs a template unless a wasm transform produces its body
+        if register.template.trim().is_empty() && register.wasm.is_none() {
+            return Err(format!("Register {}: template cannot be empty", i).into());
+        }
+
         // Validate template by trying to compile it
         let mut handlebars = handlebars::Handlebars::new();
         handlebars.register_template_string("test", &register.template)
             .map_err(|e| format!("Register {}: template error: {}", i, e))?;
+
+        // Validate target URL, method, query and header templates
+        TargetTemplates::compile(&register.target)
+            .map_err(|e| format!("Register {}: {}", i, e))?;
+
+        // Validate the response policy and compile response templates
+        crate::response_policy::CompiledResponse::compile(&register.response)
+            .map_err(|e| format!("Register {}: {}", i, e))?;
+
+        // Validate TLS and proxy settings by building the target's client
+        ClientPool::new(std::time::Duration::from_secs(30), egress.clone())?
+            .validate(&register.target)
+            .map_err(|e| format!("Register {}: {}", i, e))?;
+
+        // Validate inbound auth settings
+        if let Some(auth) = &register.auth {
+            crate::inbound_auth::InboundAuthenticator::compile(auth)
+                .map_err(|e| format!("Register {}: {}", i, e))?;
+        }
+
+        // Validate script by compiling it
+        if let Some(script) = &register.script {
+            CompiledScript::compile(script)
+                .map_err(|e| format!("Register {}: {}", i, e))?;
+        }
+
+        // Validate wasm transform by loading and compiling the module
+        if let Some(wasm) = &register.wasm {
+            WasmTransform::load(wasm)
+                .map_err(|e| format!("Register {}: {}", i, e))?;
+        }
+
+        // Validate the target probe URL and method
+        if let Some(probe) = &register.probe {
+            crate::health::probe_request(register, probe)
+                .map_err(|e| format!("Register {}: {}", i, e))?;
+        }
     }
     
     Ok(())
@@ -90,7 +329,9 @@ async fn validate_config(config_path: &PathBuf) -> Result<(), Box<dyn std::error
 async fn test_template(
     config_path: &PathBuf, 
     endpoint: &str, 
-    payload: &str
+    payload: &str,
+    content_type: Option<&str>,
+    send: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let config = Config::load(config_path).await?;
     
@@ -99,24 +340,232 @@ async fn test_template(
         .find(|r| r.endpoint == endpoint)
         .ok_or_else(|| format!("Endpoint '{}' not found", endpoint))?;
     
-    // Parse the payload
-    let payload_json: serde_json::Value = serde_json::from_str(payload)?;
-    
+    // Decode the payload the same way the server decodes inbound bodies
+    let raw_body = axum::body::Bytes::from(payload.to_string());
+    let payload_json = crate::inbound::decode_payload(content_type, &raw_body).await?;
+
+    let request = serde_json::json!({
+        "endpoint": register.endpoint,
+        "method": register.method,
+        "headers": content_type
+            .map(|ct| serde_json::json!({ "content-type": ct }))
+            .unwrap_or_else(|| serde_json::json!({})),
+        "body": payload,
+    });
+
+    // Run the register's script, if any
+    let mut target_url_override = None;
+    let mut extra_headers = Vec::new();
+    let payload_json = match &register.script {
+        Some(script) => {
+            let script = CompiledScript::compile(script)?;
+            let outcome = script.run(&request, &payload_json, &register.target.url)?;
+            if outcome.drop {
+                println!("🗑️  Event dropped by script");
+                return Ok(());
+            }
+            if outcome.target_url != register.target.url {
+                println!("📜 Script target URL: {}", outcome.target_url);
+                target_url_override = Some(outcome.target_url);
+            }
+            for (name, value) in &outcome.headers {
+                println!("📜 Script header: {}: {}", name, value);
+            }
+            extra_headers.extend(outcome.headers);
+            outcome.payload
+        }
+        None => payload_json,
+    };
+
     // Create template data
-    let template_data = crate::json_to_template_data(&payload_json);
-    
-    // Render template
-    let mut handlebars = handlebars::Handlebars::new();
-    handlebars.register_template_string("test", &register.template)?;
-    let rendered = handlebars.render("test", &template_data)?;
-    
-    println!("📝 Template rendered successfully:");
+    let mut template_data = crate::json_to_template_data(&payload_json);
+    template_data.insert("_raw".to_string(), serde_json::Value::String(payload.to_string()));
+
+    let rendered = match &register.wasm {
+        Some(wasm) => {
+            // Run the wasm transform in place of the template
+            let transform = WasmTransform::load(wasm)?;
+            let output = transform.run(&request, &payload_json)?;
+            if output.drop {
+                println!("🗑️  Event dropped by wasm transform");
+                return Ok(());
+            }
+            for (name, value) in &output.headers {
+                println!("🧩 Wasm header: {}: {}", name, value);
+            }
+            extra_headers.extend(output.headers);
+            println!("🧩 Wasm transform ran successfully:");
+            output.body
+        }
+        None => {
+            // Render template
+            let mut handlebars = handlebars::Handlebars::new();
+            handlebars.register_template_string("test", &register.template)?;
+            let rendered = handlebars.render("test", &template_data)?;
+            println!("📝 Template rendered successfully:");
+            rendered
+        }
+    };
     println!("{}", rendered);
-    
-    // Validate that rendered output is valid JSON
-    let _: serde_json::Value = serde_json::from_str(&rendered)?;
-    println!("✅ Rendered output is valid JSON");
-    
+
+    // Validate and serialize the rendered output for the target's body format
+    let format = register.target.body_format;
+    let encoded = encode_body(format, &rendered)?;
+    println!("✅ Rendered output is valid {}", format.as_str());
+    if encoded.body != rendered {
+        println!("📦 Encoded body ({}):", encoded.content_type);
+        println!("{}", encoded.body);
+    }
+
+    // Render the target request
+    let target = TargetTemplates::compile(&register.target)?
+        .render(&template_data, target_url_override.as_deref())?;
+    println!("🎯 Target: {} {}", target.method, target.url);
+    for (name, value) in &target.headers {
+        println!("🎯 Target header: {}: {}", name, value);
+    }
+
+    // Check the target against the egress policy, as the server would
+    let policy = EgressPolicy::compile(&config.settings.egress)?;
+    policy.check_url(&target.url)?;
+    if !send {
+        return Ok(());
+    }
+
+    // Send the request the same way the server does
+    let clients = ClientPool::new(std::time::Duration::from_secs(30), policy)?;
+    let prepared = clients.prepare(&register.target, target.url.clone()).await?;
+    let mut headers = reqwest::header::HeaderMap::new();
+    for (name, value) in target.headers.iter().chain(extra_headers.iter()) {
+        headers.insert(
+            reqwest::header::HeaderName::from_bytes(name.as_bytes())?,
+            reqwest::header::HeaderValue::from_str(value)?,
+        );
+    }
+    if !headers.contains_key(reqwest::header::CONTENT_TYPE) {
+        headers.insert(
+            reqwest::header::CONTENT_TYPE,
+            reqwest::header::HeaderValue::from_static(encoded.content_type),
+        );
+    }
+    if let Some(host) = prepared.host_header {
+        headers.insert(reqwest::header::HOST, reqwest::header::HeaderValue::from_str(&host)?);
+    }
+
+    let request = prepared
+        .client
+        .request(target.method, prepared.url)
+        .headers(headers)
+        .body(encoded.body);
+    let response = TokenCache::new(clients.default_client().clone())
+        .send(register.target.auth.as_ref(), request)
+        .await?;
+    println!("📨 Target responded {}", response.status());
+    println!("{}", response.text().await?);
+
+    Ok(())
+}
+
+async fn replay(
+    config_path: &PathBuf,
+    mut files: Vec<PathBuf>,
+    filter: &crate::replay::ReplayFilter,
+    send: bool,
+    rate: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !(rate.is_finite() && rate > 0.0) {
+        return Err(format!("Invalid rate {}: expected requests per second above 0", rate).into());
+    }
+    let (config, source) = Config::load_with_source(config_path).await?;
+    if files.is_empty() {
+        let log = config
+            .settings
+            .delivery_log
+            .as_ref()
+            .ok_or("No --from files given and no delivery log configured")?;
+        files = crate::delivery_log::log_files(log);
+    }
+
+    // Compile the registers as the server would
+    let egress = EgressPolicy::compile(&config.settings.egress)?;
+    let clients = std::sync::Arc::new(ClientPool::new(std::time::Duration::from_secs(30), egress)?);
+    let sources = source["registers"].as_sequence().cloned().unwrap_or_default();
+    let routes = crate::routes::RouteTable::new(sources, &config.settings, clients.clone(), None)?.current();
+    let token_cache = TokenCache::new(clients.default_client().clone());
+    let default_retry = config.settings.default_retry();
+    let request_id_header = config
+        .settings
+        .request_id_header
+        .as_deref()
+        .map(crate::request_id::forward_header)
+        .transpose()?;
+
+    let (events, skipped) = crate::replay::read(&files, filter)?;
+    for reason in skipped.iter().take(5) {
+        println!("⚠️  Skipped {}", reason);
+    }
+    if skipped.len() > 5 {
+        println!("⚠️  Skipped {} more lines", skipped.len() - 5);
+    }
+    println!("🔁 {} event(s) to replay{}", events.len(), if send { "" } else { " (dry run, pass --send to send)" });
+
+    let (mut replayed, mut dropped, mut failed) = (0, 0, 0);
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs_f64(1.0 / rate));
+    for event in &events {
+        let label = format!(
+            "{} {} [{}]",
+            event.timestamp.map(|t| t.to_rfc3339()).unwrap_or_else(|| "-".to_string()),
+            event.endpoint,
+            event.request_id.as_deref().unwrap_or("-")
+        );
+        let Some(route) = routes.get(&event.endpoint) else {
+            println!("❌ {}: no register for this endpoint", label);
+            failed += 1;
+            continue;
+        };
+        let request = match crate::replay::render(route, event).await {
+            Ok(Some(request)) => request,
+            Ok(None) => {
+                println!("🗑️  {}: dropped by script or wasm transform", label);
+                dropped += 1;
+                continue;
+            }
+            Err(e) => {
+                println!("❌ {}: {}", label, e);
+                failed += 1;
+                continue;
+            }
+        };
+        println!("🎯 {} → {} {}", label, request.method, request.url);
+        if !send {
+            println!("{}", request.body);
+            replayed += 1;
+            continue;
+        }
+
+        interval.tick().await;
+        let request_id = request_id_header.as_ref().zip(event.request_id.as_deref());
+        match crate::replay::send(&clients, &token_cache, route, &default_retry, request, request_id).await {
+            Ok(response) if response.status().is_success() => {
+                println!("📨 Target responded {}", response.status());
+                replayed += 1;
+            }
+            Ok(response) => {
+                println!("❌ Target responded {}", response.status());
+                failed += 1;
+            }
+            Err(e) => {
+                println!("❌ {}", crate::secrets::redact(&e));
+                failed += 1;
+            }
+        }
+    }
+
+    let action = if send { "Sent" } else { "Rendered" };
+    println!("✅ {} {}, dropped {}, failed {}", action, replayed, dropped, failed);
+    if failed > 0 {
+        return Err(format!("{} event(s) failed to replay", failed).into());
+    }
     Ok(())
 }
 
@@ -124,7 +573,7 @@ async fn list_endpoints(config_path: &PathBuf) -> Result<(), Box<dyn std::error:
     let config = Config::load(config_path).await?;
     
     println!("📋 Registered webhook endpoints:");
-    println!("{:<8} {:<30} {:<8} {}", "METHOD", "ENDPOINT", "TARGET", "URL");
+    println!("{:<8} {:<30} {:<8} URL", "METHOD", "ENDPOINT", "TARGET");
     println!("{}", "-".repeat(80));
     
     for register in &config.registers {
@@ -140,5 +589,166 @@ async fn list_endpoints(config_path: &PathBuf) -> Result<(), Box<dyn std::error:
     Ok(())
 }
 
+impl Remote {
+    fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = reqwest::Client::new().request(method, format!("{}{}", self.url.trim_end_matches('/'), path));
+        match &self.token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+}
+
+/// Send an admin API request, failing on a non-2xx answer.
+async fn send_admin(request: reqwest::RequestBuilder) -> Result<reqwest::Response, Box<dyn std::error::Error>> {
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|body| body["error"].as_str().map(str::to_string))
+            .unwrap_or(body);
+        return Err(format!("Admin API returned {}: {}", status, message).into());
+    }
+    Ok(response)
+}
+
+async fn registers(remote: &Remote, action: RegisterAction) -> Result<(), Box<dyn std::error::Error>> {
+    use reqwest::Method;
+
+    let path = |endpoint: &str| format!("/admin/registers/{}", endpoint.trim_start_matches('/'));
+    let read = |file: &PathBuf| -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        Ok(serde_yaml::from_str(&std::fs::read_to_string(file)?)?)
+    };
+
+    match action {
+        RegisterAction::List => {
+            let body: serde_json::Value = send_admin(remote.request(Method::GET, "/admin/registers")).await?.json().await?;
+            println!("📋 Registers being served:");
+            println!("{:<8} {:<30} {:<9} URL", "METHOD", "ENDPOINT", "STATUS");
+            println!("{}", "-".repeat(80));
+            for register in body["registers"].as_array().into_iter().flatten() {
+                println!(
+                    "{:<8} {:<30} {:<9} {}",
+                    register["method"].as_str().unwrap_or_default(),
+                    register["endpoint"].as_str().unwrap_or_default(),
+                    if register["enabled"] == false { "disabled" } else { "enabled" },
+                    register["target"]["url"].as_str().unwrap_or_default()
+                );
+            }
+        }
+        RegisterAction::Get { endpoint } => {
+            let body: serde_json::Value = send_admin(remote.request(Method::GET, &path(&endpoint))).await?.json().await?;
+            print!("{}", serde_yaml::to_string(&body)?);
+        }
+        RegisterAction::Create { file, dry_run } => {
+            let request = remote
+                .request(Method::POST, "/admin/registers")
+                .query(&[("dry_run", dry_run)])
+                .json(&read(&file)?);
+            let body: serde_json::Value = send_admin(request).await?.json().await?;
+            let endpoint = body["endpoint"].as_str().unwrap_or_default();
+            match dry_run {
+                true => println!("✅ Register {} is valid", endpoint),
+                false => println!("✅ Created register {}", endpoint),
+            }
+        }
+        RegisterAction::Update { endpoint, file, dry_run } => {
+            let request = remote
+                .request(Method::PUT, &path(&endpoint))
+                .query(&[("dry_run", dry_run)])
+                .json(&read(&file)?);
+            send_admin(request).await?;
+            match dry_run {
+                true => println!("✅ Update of register {} is valid", endpoint),
+                false => println!("✅ Updated register {}", endpoint),
+            }
+        }
+        RegisterAction::Delete { endpoint } => {
+            send_admin(remote.request(Method::DELETE, &path(&endpoint))).await?;
+            println!("🗑️  Deleted register {}", endpoint);
+        }
+        RegisterAction::Enable { endpoint } => {
+            send_admin(remote.request(Method::POST, &format!("{}/enable", path(&endpoint)))).await?;
+            println!("✅ Enabled register {}", endpoint);
+        }
+        RegisterAction::Disable { endpoint } => {
+            send_admin(remote.request(Method::POST, &format!("{}/disable", path(&endpoint)))).await?;
+            println!("⏸️  Disabled register {}", endpoint);
+        }
+    }
+    Ok(())
+}
 
+async fn send_pause(
+    remote: &Remote,
+    path: &str,
+    scope: crate::pause::Scope,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let request = remote.request(reqwest::Method::POST, path).json(&scope);
+    Ok(send_admin(request).await?.json().await?)
+}
+
+fn describe_scope(scope: &serde_json::Value) -> String {
+    match (scope["register"].as_str(), scope["target"].as_str()) {
+        (Some(endpoint), _) => format!("register {}", endpoint),
+        (None, Some(target)) => format!("target {}", target),
+        _ => scope.to_string(),
+    }
+}
+
+async fn paused(remote: &Remote) -> Result<(), Box<dyn std::error::Error>> {
+    let status: serde_json::Value = send_admin(remote.request(reqwest::Method::GET, "/admin/pause")).await?.json().await?;
+    let list = |key: &str| {
+        let items: Vec<_> = status[key].as_array().into_iter().flatten().filter_map(|v| v.as_str()).collect();
+        if items.is_empty() { "none".to_string() } else { items.join(", ") }
+    };
+    println!("⏸️  Paused registers: {}", list("registers"));
+    println!("⏸️  Paused targets: {}", list("targets"));
+    println!(
+        "📦 Buffered: {}/{} (mode: {})",
+        status["buffered"],
+        status["buffer_capacity"],
+        status["mode"].as_str().unwrap_or_default()
+    );
+    Ok(())
+}
 
+async fn tail(remote: &Remote, endpoint: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut request = remote.request(reqwest::Method::GET, "/admin/tail");
+    if let Some(endpoint) = endpoint {
+        request = request.query(&[("endpoint", endpoint)]);
+    }
+    let response = send_admin(request).await?;
+
+    println!("👀 Following deliveries{} (Ctrl+C to stop)", endpoint.map(|e| format!(" on {}", e)).unwrap_or_default());
+    crate::tail::follow(response, |event| {
+        let icon = match event.outcome {
+            Outcome::Delivered => "✅",
+            Outcome::Failed => "❌",
+            Outcome::Dropped => "🗑️",
+            Outcome::Buffered => "⏸️",
+        };
+        let status = event.status.map(|s| s.to_string()).unwrap_or_else(|| "-".to_string());
+        println!(
+            "{} {} {} → {} {} {}ms [{}]",
+            icon,
+            event.timestamp.format("%H:%M:%S%.3f"),
+            event.endpoint,
+            event.route.as_deref().unwrap_or("-"),
+            status,
+            event.latency_ms,
+            event.request_id
+        );
+        if let Some(preview) = &event.preview {
+            println!("    {}", preview);
+        }
+        if let Some(error) = &event.error {
+            println!("    error: {}", error);
+        }
+    })
+    .await?;
+    println!("Stream closed by server");
+    Ok(())
+}
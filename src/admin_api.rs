@@ -0,0 +1,446 @@
+//! The `/admin` HTTP API, served when `settings.admin` is configured, on the
+//! main listener or on `settings.admin.bind_address`. Every route requires the
+//! credentials in `settings.admin.auth`, including those reading the `/debug`
+//! request bin's captures.
+
+use crate::debug_bin::DebugBin;
+use crate::delivery_log::{DeliveryFilter, DeliveryLog};
+use crate::inbound_auth::InboundAuthenticator;
+use crate::pause::{PauseControl, Scope};
+use crate::routes::{Change, ChangeError, RouteTable};
+use crate::tail::Tail;
+use axum::{
+    extract::{rejection::JsonRejection, Path, Query, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{get, post},
+    Router,
+};
+use futures_util::Stream;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+#[derive(Clone)]
+pub struct AdminState {
+    pub auth: Arc<InboundAuthenticator>,
+    pub delivery_log: Option<Arc<DeliveryLog>>,
+    pub tail: Tail,
+    pub routes: Arc<RouteTable>,
+    pub pause: Arc<PauseControl>,
+    pub debug_bin: Arc<DebugBin>,
+}
+
+pub fn router(state: AdminState) -> Router {
+    Router::new()
+        .route("/admin/deliveries", get(list_deliveries))
+        .route("/admin/tail", get(tail))
+        .route("/admin/registers", get(list_registers).post(create_register))
+        .route(
+            "/admin/registers/*endpoint",
+            get(get_register)
+                .put(update_register)
+                .delete(delete_register)
+                .post(set_register_enabled),
+        )
+        .route("/admin/pause", get(pause_status).post(pause))
+        .route("/admin/resume", post(resume))
+        .merge(crate::debug_bin::admin_router(state.debug_bin.clone()))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_auth))
+        .with_state(state)
+}
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(json!({ "error": crate::secrets::redact(&message.into()) }))).into_response()
+}
+
+async fn require_auth(State(state): State<AdminState>, request: Request, next: Next) -> Response {
+    let query = request.uri().query();
+    if let Err(reason) = state.auth.check(request.headers(), query) {
+        warn!(path = %request.uri().path(), reason, "Admin authentication failed");
+        return error(StatusCode::UNAUTHORIZED, "Unauthorized");
+    }
+    next.run(request).await
+}
+
+/// `GET /admin/deliveries?endpoint=&status=&since=&until=&limit=`
+async fn list_deliveries(
+    State(state): State<AdminState>,
+    filter: Result<Query<DeliveryFilter>, axum::extract::rejection::QueryRejection>,
+) -> Result<Json<Value>, Response> {
+    let Query(filter) = filter.map_err(|e| error(StatusCode::BAD_REQUEST, e.body_text()))?;
+    let log = state
+        .delivery_log
+        .clone()
+        .ok_or_else(|| error(StatusCode::NOT_FOUND, "Delivery log is not enabled"))?;
+
+    let deliveries = tokio::task::spawn_blocking(move || log.query(&filter))
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|result| result)
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(Json(json!({ "deliveries": deliveries })))
+}
+
+#[derive(Debug, Deserialize)]
+struct TailQuery {
+    endpoint: Option<String>,
+}
+
+/// `GET /admin/tail?endpoint=`: processed webhooks as Server-Sent Events.
+async fn tail(
+    State(state): State<AdminState>,
+    Query(query): Query<TailQuery>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    Sse::new(state.tail.subscribe(query.endpoint)).keep_alive(KeepAlive::default())
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangeQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// `GET /admin/registers`: every register as written, with `${...}`
+/// references unresolved.
+async fn list_registers(State(state): State<AdminState>) -> Json<Value> {
+    let registers: Vec<_> = state.routes.current().iter().map(|route| route.view()).collect();
+    Json(json!({ "registers": registers }))
+}
+
+async fn get_register(State(state): State<AdminState>, Path(endpoint): Path<String>) -> Result<Json<Value>, Response> {
+    let endpoint = format!("/{}", endpoint);
+    match state.routes.current().get(&endpoint) {
+        Some(route) => Ok(Json(route.view())),
+        None => Err(change_error(ChangeError::NotFound(endpoint))),
+    }
+}
+
+/// `POST /admin/registers?dry_run=`
+async fn create_register(
+    State(state): State<AdminState>,
+    Query(query): Query<ChangeQuery>,
+    body: Result<Json<Value>, JsonRejection>,
+) -> Result<Response, Response> {
+    let source = register_source(body).map_err(|e| error(StatusCode::BAD_REQUEST, e))?;
+    let route = apply(&state, Change::Create(source), query.dry_run).await?;
+    let status = if query.dry_run { StatusCode::OK } else { StatusCode::CREATED };
+    Ok((status, Json(route)).into_response())
+}
+
+/// `PUT /admin/registers/{endpoint}?dry_run=`, which may also rename it.
+async fn update_register(
+    State(state): State<AdminState>,
+    Path(endpoint): Path<String>,
+    Query(query): Query<ChangeQuery>,
+    body: Result<Json<Value>, JsonRejection>,
+) -> Result<Json<Value>, Response> {
+    let source = register_source(body).map_err(|e| error(StatusCode::BAD_REQUEST, e))?;
+    let change = Change::Update(format!("/{}", endpoint), source);
+    Ok(Json(apply(&state, change, query.dry_run).await?))
+}
+
+async fn delete_register(State(state): State<AdminState>, Path(endpoint): Path<String>) -> Result<StatusCode, Response> {
+    let endpoint = format!("/{}", endpoint);
+    apply(&state, Change::Delete(endpoint.clone()), false).await?;
+    info!(endpoint = %endpoint, "Deleted register");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /admin/registers/{endpoint}/enable` and `.../disable`
+async fn set_register_enabled(
+    State(state): State<AdminState>,
+    Path(path): Path<String>,
+) -> Result<Json<Value>, Response> {
+    let (endpoint, enabled) = match path.rsplit_once('/') {
+        Some((endpoint, "enable")) => (endpoint, true),
+        Some((endpoint, "disable")) => (endpoint, false),
+        _ => return Err(error(StatusCode::NOT_FOUND, "Expected /enable or /disable")),
+    };
+    let change = Change::SetEnabled(format!("/{}", endpoint), enabled);
+    Ok(Json(apply(&state, change, false).await?))
+}
+
+/// `GET /admin/pause`
+async fn pause_status(State(state): State<AdminState>) -> Json<Value> {
+    Json(state.pause.status())
+}
+
+/// `POST /admin/pause` with `{"register": "/endpoint"}` or `{"target": "https://host"}`
+async fn pause(State(state): State<AdminState>, body: Result<Json<Scope>, JsonRejection>) -> Result<Json<Value>, Response> {
+    let Json(scope) = body.map_err(|e| error(StatusCode::BAD_REQUEST, e.body_text()))?;
+    let scope = state.pause.pause(scope).map_err(|e| error(StatusCode::BAD_REQUEST, e))?;
+    Ok(Json(json!({ "paused": scope, "status": state.pause.status() })))
+}
+
+/// `POST /admin/resume`, taking the same body as `/admin/pause`
+async fn resume(State(state): State<AdminState>, body: Result<Json<Scope>, JsonRejection>) -> Result<Json<Value>, Response> {
+    let Json(scope) = body.map_err(|e| error(StatusCode::BAD_REQUEST, e.body_text()))?;
+    let scope = state.pause.resume(scope).map_err(|e| error(StatusCode::BAD_REQUEST, e))?;
+    Ok(Json(json!({ "resumed": scope, "status": state.pause.status() })))
+}
+
+fn register_source(body: Result<Json<Value>, JsonRejection>) -> Result<serde_yaml::Value, String> {
+    let Json(body) = body.map_err(|e| e.body_text())?;
+    serde_yaml::to_value(body).map_err(|e| e.to_string())
+}
+
+/// Apply a change off the async runtime, as it compiles and writes files.
+async fn apply(state: &AdminState, change: Change, dry_run: bool) -> Result<Value, Response> {
+    let routes = state.routes.clone();
+    let route = tokio::task::spawn_blocking(move || routes.apply(change, dry_run))
+        .await
+        .map_err(|e| error(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(change_error)?;
+    if let Some(route) = &route {
+        let action = if dry_run { "Validated" } else { "Applied" };
+        info!(endpoint = %route.register.endpoint, "{} register change", action);
+    }
+    Ok(route.map(|route| route.view()).unwrap_or(Value::Null))
+}
+
+fn change_error(e: ChangeError) -> Response {
+    let status = match e {
+        ChangeError::NotFound(_) => StatusCode::NOT_FOUND,
+        ChangeError::Conflict(_) => StatusCode::CONFLICT,
+        ChangeError::Invalid(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        ChangeError::Persist(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    error(status, e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{DeliveryLogConfig, InboundAuth};
+    use crate::delivery_log::{DeliveryRecord, InboundRecord};
+
+    fn routes() -> Arc<RouteTable> {
+        let egress = crate::egress::EgressPolicy::compile(&Default::default()).unwrap();
+        let clients = crate::client_pool::ClientPool::new(std::time::Duration::from_secs(5), egress).unwrap();
+        Arc::new(RouteTable::new(Vec::new(), &Default::default(), Arc::new(clients), None).unwrap())
+    }
+
+    async fn serve(state: AdminState) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router(state)).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_deliveries_require_auth_and_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = DeliveryLog::open(&DeliveryLogConfig {
+            path: dir.path().join("deliveries.jsonl"),
+            max_file_bytes: 1024 * 1024,
+            max_files: 1,
+            max_age_hours: None,
+            store_bodies: false,
+        })
+        .unwrap();
+        for (endpoint, status) in [("/github", 200), ("/alerts", 503)] {
+            let inbound = InboundRecord {
+                method: "POST".to_string(),
+                source: "127.0.0.1".to_string(),
+                content_type: None,
+                body_bytes: 0,
+                headers: Default::default(),
+                body: None,
+            };
+            let mut record = DeliveryRecord::new("req", chrono::Utc::now(), endpoint, inbound);
+            record.status = Some(status);
+            record.finish(None);
+            log.record(record);
+        }
+        log.flush();
+
+        let auth: InboundAuth = serde_yaml::from_str("bearer: { tokens: [admin-token] }").unwrap();
+        let base = serve(AdminState {
+            auth: Arc::new(InboundAuthenticator::compile(&auth).unwrap()),
+            delivery_log: Some(Arc::new(log)),
+            tail: Tail::default(),
+            routes: routes(),
+            pause: Arc::new(PauseControl::new(&Default::default()).unwrap()),
+            debug_bin: Arc::new(DebugBin::new(&Default::default()).unwrap()),
+        })
+        .await;
+        let client = reqwest::Client::new();
+
+        let response = client.get(format!("{}/admin/deliveries", base)).send().await.unwrap();
+        assert_eq!(response.status(), 401);
+        let response = client.get(format!("{}/debug/requests", base)).send().await.unwrap();
+        assert_eq!(response.status(), 401);
+
+        let body: Value = client
+            .get(format!("{}/admin/deliveries?status=5xx", base))
+            .bearer_auth("admin-token")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["deliveries"].as_array().unwrap().len(), 1);
+        assert_eq!(body["deliveries"][0]["endpoint"], "/alerts");
+
+        let response = client
+            .get(format!("{}/admin/deliveries?since=yesterday", base))
+            .bearer_auth("admin-token")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+    }
+
+    #[tokio::test]
+    async fn test_tail_streams_events_for_endpoint() {
+        let auth: InboundAuth = serde_yaml::from_str("bearer: { tokens: [admin-token] }").unwrap();
+        let tail = Tail::default();
+        let base = serve(AdminState {
+            auth: Arc::new(InboundAuthenticator::compile(&auth).unwrap()),
+            delivery_log: None,
+            tail: tail.clone(),
+            routes: routes(),
+            pause: Arc::new(PauseControl::new(&Default::default()).unwrap()),
+            debug_bin: Arc::new(DebugBin::new(&Default::default()).unwrap()),
+        })
+        .await;
+
+        let response = reqwest::Client::new()
+            .get(format!("{}/admin/tail?endpoint=/github", base))
+            .bearer_auth("admin-token")
+            .send()
+            .await
+            .unwrap();
+        let (sender, mut events) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(crate::tail::follow(response, move |event| {
+            let _ = sender.send(event);
+        }));
+
+        for endpoint in ["/alerts", "/github"] {
+            let inbound = InboundRecord {
+                method: "POST".to_string(),
+                source: "127.0.0.1".to_string(),
+                content_type: None,
+                body_bytes: 0,
+                headers: Default::default(),
+                body: None,
+            };
+            let mut record = DeliveryRecord::new("req", chrono::Utc::now(), endpoint, inbound);
+            record.status = Some(502);
+            record.finish(None);
+            tail.publish(&record);
+        }
+
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), events.recv()).await.unwrap().unwrap();
+        assert_eq!(event.endpoint, "/github");
+        assert_eq!(event.status, Some(502));
+    }
+
+    #[tokio::test]
+    async fn test_register_changes() {
+        let auth: InboundAuth = serde_yaml::from_str("bearer: { tokens: [admin-token] }").unwrap();
+        let base = serve(AdminState {
+            auth: Arc::new(InboundAuthenticator::compile(&auth).unwrap()),
+            delivery_log: None,
+            tail: Tail::default(),
+            routes: routes(),
+            pause: Arc::new(PauseControl::new(&Default::default()).unwrap()),
+            debug_bin: Arc::new(DebugBin::new(&Default::default()).unwrap()),
+        })
+        .await;
+        let client = reqwest::Client::new();
+        let register = |template: &str| {
+            json!({
+                "endpoint": "/webhook/github",
+                "method": "POST",
+                "target": { "url": "https://chat.example.com/hooks", "method": "POST" },
+                "template": template,
+            })
+        };
+
+        let response = client
+            .post(format!("{}/admin/registers", base))
+            .bearer_auth("admin-token")
+            .json(&register("{{#each}}"))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 422);
+
+        let response = client
+            .post(format!("{}/admin/registers", base))
+            .bearer_auth("admin-token")
+            .json(&register(r#"{"event": "{{action}}"}"#))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 201);
+
+        let body: Value = client
+            .post(format!("{}/admin/registers/webhook/github/disable", base))
+            .bearer_auth("admin-token")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["enabled"], false);
+
+        let body: Value = client
+            .get(format!("{}/admin/registers", base))
+            .bearer_auth("admin-token")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["registers"][0]["endpoint"], "/webhook/github");
+        assert_eq!(body["registers"][0]["enabled"], false);
+
+        let response = client
+            .delete(format!("{}/admin/registers/webhook/github", base))
+            .bearer_auth("admin-token")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 204);
+        let response = client
+            .get(format!("{}/admin/registers/webhook/github", base))
+            .bearer_auth("admin-token")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+
+        let body: Value = client
+            .post(format!("{}/admin/pause", base))
+            .bearer_auth("admin-token")
+            .json(&json!({ "target": "https://chat.example.com/hooks" }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["status"]["targets"][0], "https://chat.example.com");
+        let response = client
+            .post(format!("{}/admin/resume", base))
+            .bearer_auth("admin-token")
+            .json(&json!({ "endpoint": "/webhook/github" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::config::BodyFormat;
+use quick_xml::events::Event;
+use serde_json::Value;
+
+/// A rendered body, validated and serialized for its target.
+#[derive(Debug, Clone)]
+pub struct EncodedBody {
+    pub body: String,
+    pub content_type: &'static str,
+}
+
+impl BodyFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BodyFormat::Json => "json",
+            BodyFormat::Form => "form",
+            BodyFormat::Text => "text",
+            BodyFormat::Xml => "xml",
+            BodyFormat::Raw => "raw",
+        }
+    }
+
+    /// Content-Type used when the target does not set one explicitly.
+    pub fn default_content_type(&self) -> &'static str {
+        match self {
+            BodyFormat::Json => "application/json",
+            BodyFormat::Form => "application/x-www-form-urlencoded",
+            BodyFormat::Text => "text/plain; charset=utf-8",
+            BodyFormat::Xml => "application/xml",
+            BodyFormat::Raw => "application/octet-stream",
+        }
+    }
+}
+
+/// Validate a rendered template and serialize it according to `format`.
+pub fn encode_body(format: BodyFormat, rendered: &str) -> Result<EncodedBody, String> {
+    let body = match format {
+        BodyFormat::Json => {
+            let value: Value = serde_json::from_str(rendered)
+                .map_err(|e| format!("Rendered template is not valid JSON: {}", e))?;
+            value.to_string()
+        }
+        BodyFormat::Form => encode_form(rendered)?,
+        BodyFormat::Xml => {
+            validate_xml(rendered)?;
+            rendered.to_string()
+        }
+        BodyFormat::Text | BodyFormat::Raw => rendered.to_string(),
+    };
+
+    Ok(EncodedBody {
+        body,
+        content_type: format.default_content_type(),
+    })
+}
+
+/// Form bodies are rendered either as a flat JSON object, which is encoded
+/// here, or directly as `key=value&...`, which is only checked.
+fn encode_form(rendered: &str) -> Result<String, String> {
+    let trimmed = rendered.trim();
+    if !trimmed.starts_with('{') {
+        serde_urlencoded::from_str::<Vec<(String, String)>>(trimmed)
+            .map_err(|e| format!("Rendered template is not valid form data: {}", e))?;
+        return Ok(trimmed.to_string());
+    }
+
+    let value: Value = serde_json::from_str(trimmed)
+        .map_err(|e| format!("Rendered template is not valid JSON: {}", e))?;
+    let Value::Object(map) = value else {
+        return Err("Rendered form template must be a JSON object".to_string());
+    };
+
+    let mut pairs = Vec::new();
+    for (key, value) in &map {
+        match value {
+            Value::Array(items) => {
+                for item in items {
+                    pairs.push((key.as_str(), form_scalar(key, item)?));
+                }
+            }
+            other => pairs.push((key.as_str(), form_scalar(key, other)?)),
+        }
+    }
+
+    serde_urlencoded::to_string(pairs).map_err(|e| format!("Failed to encode form body: {}", e))
+}
+
+fn form_scalar(key: &str, value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Null => Ok(String::new()),
+        Value::Bool(_) | Value::Number(_) => Ok(value.to_string()),
+        Value::Array(_) | Value::Object(_) => {
+            Err(format!("Form field '{}' must be a scalar or a list of scalars", key))
+        }
+    }
+}
+
+fn validate_xml(rendered: &str) -> Result<(), String> {
+    let mut reader = quick_xml::Reader::from_str(rendered);
+    let mut depth = 0usize;
+    let mut roots = 0usize;
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(_)) => {
+                if depth == 0 {
+                    roots += 1;
+                }
+                depth += 1;
+            }
+            Ok(Event::Empty(_)) if depth == 0 => roots += 1,
+            Ok(Event::End(_)) => depth = depth.saturating_sub(1),
+            Ok(Event::Eof) => break,
+            Ok(_) => {}
+            Err(e) => {
+                return Err(format!(
+                    "Rendered template is not valid XML at position {}: {}",
+                    reader.error_position(),
+                    e
+                ))
+            }
+        }
+    }
+
+    if depth != 0 {
+        return Err("Rendered template is not valid XML: unclosed element".to_string());
+    }
+    if roots != 1 {
+        return Err("Rendered template is not valid XML: expected a single root element".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_form_from_json_object() {
+        let encoded = encode_body(
+            BodyFormat::Form,
+            r#"{"To": "+15551234", "Body": "disk 95% full", "tags": ["a", "b"], "count": 3}"#,
+        )
+        .unwrap();
+        assert_eq!(encoded.body, "Body=disk+95%25+full&To=%2B15551234&count=3&tags=a&tags=b");
+        assert_eq!(encoded.content_type, "application/x-www-form-urlencoded");
+    }
+
+    #[test]
+    fn test_encode_form_passthrough_and_nested_error() {
+        let encoded = encode_body(BodyFormat::Form, "token=abc&job=build\n").unwrap();
+        assert_eq!(encoded.body, "token=abc&job=build");
+
+        let err = encode_body(BodyFormat::Form, r#"{"a": {"b": 1}}"#).unwrap_err();
+        assert!(err.contains("scalar"));
+    }
+
+    #[test]
+    fn test_encode_xml() {
+        let xml = r#"<?xml version="1.0"?><soap:Envelope xmlns:soap="urn:x"><soap:Body>hi</soap:Body></soap:Envelope>"#;
+        let encoded = encode_body(BodyFormat::Xml, xml).unwrap();
+        assert_eq!(encoded.body, xml);
+        assert_eq!(encoded.content_type, "application/xml");
+
+        assert!(encode_body(BodyFormat::Xml, "<a><b></a>").is_err());
+        assert!(encode_body(BodyFormat::Xml, "<a>").is_err());
+        assert!(encode_body(BodyFormat::Xml, "<a/><b/>").is_err());
+    }
+
+    #[test]
+    fn test_encode_json_and_text() {
+        assert!(encode_body(BodyFormat::Json, "{not json}").is_err());
+        let encoded = encode_body(BodyFormat::Text, "{not json}").unwrap();
+        assert_eq!(encoded.body, "{not json}");
+        assert_eq!(encoded.content_type, "text/plain; charset=utf-8");
+    }
+}
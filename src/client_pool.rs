@@ -0,0 +1,339 @@
+use crate::config::{Target, TargetProxy, TargetTls};
+use crate::egress::{EgressPolicy, EgressResolver};
+use reqwest::{redirect, Certificate, Client, ClientBuilder, Identity, Proxy, Url};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// HTTP clients for targets, one per distinct TLS/proxy configuration.
+///
+/// Targets without `tls` or `proxy` settings share the default client. All
+/// clients resolve hosts through the egress policy.
+#[derive(Debug)]
+pub struct ClientPool {
+    timeout: Duration,
+    policy: Arc<EgressPolicy>,
+    default: Client,
+    clients: Mutex<HashMap<String, Client>>,
+}
+
+/// A client and URL ready to send a target request with.
+#[derive(Debug, Clone)]
+pub struct PreparedTarget {
+    pub client: Client,
+    pub url: Url,
+    /// Host header to send when the URL host was replaced for SNI
+    pub host_header: Option<String>,
+}
+
+impl ClientPool {
+    pub fn new(timeout: Duration, policy: EgressPolicy) -> Result<Self, String> {
+        let policy = Arc::new(policy);
+        let default = client_builder(timeout, &policy, None, None)?
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        Ok(Self {
+            timeout,
+            policy,
+            default,
+            clients: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn default_client(&self) -> &Client {
+        &self.default
+    }
+
+    /// Check that a target's TLS and proxy settings produce a usable client,
+    /// e.g. that certificate files exist and parse.
+    pub fn validate(&self, target: &Target) -> Result<(), String> {
+        if target.tls.is_none() && target.proxy.is_none() {
+            return Ok(());
+        }
+        client_builder(self.timeout, &self.policy, target.tls.as_ref(), target.proxy.as_ref())?
+            .build()
+            .map(|_| ())
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))
+    }
+
+    /// Pick the client for a target request to `url`, after checking the URL
+    /// against the egress policy. With a proxy, the target host is resolved
+    /// and its addresses checked here, as the proxy connects to it.
+    ///
+    /// With `tls.server_name` set, the URL host is replaced by the server name
+    /// (so it is used for SNI and certificate checks) and the client is pinned
+    /// to the addresses of the original host, resolved when the client is
+    /// first created.
+    pub async fn prepare(&self, target: &Target, url: Url) -> Result<PreparedTarget, String> {
+        self.policy.check_url(&url)?;
+
+        let (tls, proxy) = (target.tls.as_ref(), target.proxy.as_ref());
+        if proxy.is_some() {
+            self.policy.check_proxied(&url).await?;
+        }
+        if tls.is_none() && proxy.is_none() {
+            return Ok(PreparedTarget {
+                client: self.default.clone(),
+                url,
+                host_header: None,
+            });
+        }
+
+        let server_name = tls
+            .and_then(|tls| tls.server_name.as_deref())
+            .filter(|name| Some(*name) != url.host_str());
+        let original_host = server_name.map(|_| {
+            (
+                url.host_str().unwrap_or_default().to_string(),
+                url.port_or_known_default().unwrap_or(443),
+            )
+        });
+
+        let key = serde_json::json!({ "tls": tls, "proxy": proxy, "host": original_host }).to_string();
+        let cached = self
+            .clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+            .cloned();
+        let client = match cached {
+            Some(client) => client,
+            None => {
+                let mut builder = client_builder(self.timeout, &self.policy, tls, proxy)?;
+                if let (Some(name), Some((host, port))) = (server_name, &original_host) {
+                    // Pinned addresses skip the resolver, so check them here
+                    let addrs = self.policy.resolve(host, *port).await?;
+                    builder = builder.resolve_to_addrs(name, &addrs);
+                }
+                let client = builder
+                    .build()
+                    .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+                self.clients
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(key, client.clone());
+                client
+            }
+        };
+
+        let mut url = url;
+        let mut host_header = None;
+        if let Some(name) = server_name {
+            host_header = Some(match url.port() {
+                Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+                None => url.host_str().unwrap_or_default().to_string(),
+            });
+            url.set_host(Some(name))
+                .map_err(|e| format!("Invalid TLS server name '{}': {}", name, e))?;
+        }
+
+        Ok(PreparedTarget {
+            client,
+            url,
+            host_header,
+        })
+    }
+}
+
+/// Follow redirects while the egress policy allows each hop. Redirects to
+/// host names are checked by the resolver as well, but literal IP hosts never
+/// reach it. Behind a proxy, which resolves redirect hosts itself, redirects
+/// are not followed.
+fn redirect_policy(policy: Arc<EgressPolicy>, proxied: bool) -> redirect::Policy {
+    if proxied {
+        return redirect::Policy::none();
+    }
+    redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= MAX_REDIRECTS {
+            return attempt.error("too many redirects");
+        }
+        match policy.check_url(attempt.url()) {
+            Ok(()) => attempt.follow(),
+            Err(e) => attempt.error(e),
+        }
+    })
+}
+
+const MAX_REDIRECTS: usize = 10;
+
+fn client_builder(
+    timeout: Duration,
+    policy: &Arc<EgressPolicy>,
+    tls: Option<&TargetTls>,
+    proxy: Option<&TargetProxy>,
+) -> Result<ClientBuilder, String> {
+    let mut builder = Client::builder()
+        .timeout(timeout)
+        .dns_resolver(Arc::new(EgressResolver(policy.clone())))
+        .redirect(redirect_policy(policy.clone(), proxy.is_some()));
+
+    if let Some(tls) = tls {
+        builder = builder.use_rustls_tls();
+
+        if let Some(path) = &tls.ca_bundle {
+            let pem = read_file(path)?;
+            let certificates = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| format!("Invalid CA bundle {}: {}", path.display(), e))?;
+            if certificates.is_empty() {
+                return Err(format!("CA bundle {} contains no certificates", path.display()));
+            }
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+            if tls.ca_bundle_only {
+                builder = builder.tls_built_in_root_certs(false);
+            }
+        }
+
+        match (&tls.client_cert, &tls.client_key) {
+            (Some(cert), Some(key)) => {
+                let mut pem = read_file(cert)?;
+                pem.push(b'\n');
+                pem.extend(read_file(key)?);
+                let identity = Identity::from_pem(&pem)
+                    .map_err(|e| format!("Invalid client certificate {}: {}", cert.display(), e))?;
+                builder = builder.identity(identity);
+            }
+            (None, None) => {}
+            _ => return Err("tls.client_cert and tls.client_key must be set together".to_string()),
+        }
+
+        if let Some(version) = &tls.min_version {
+            let version = match version.as_str() {
+                "1.2" => reqwest::tls::Version::TLS_1_2,
+                "1.3" => reqwest::tls::Version::TLS_1_3,
+                other => return Err(format!("Unsupported tls.min_version '{}', expected 1.2 or 1.3", other)),
+            };
+            builder = builder.min_tls_version(version);
+        }
+
+        if tls.insecure_skip_verify {
+            tracing::warn!("TLS certificate verification is disabled for a target");
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+    }
+
+    if let Some(proxy) = proxy {
+        let mut p = Proxy::all(&proxy.url).map_err(|e| format!("Invalid proxy URL '{}': {}", proxy.url, e))?;
+        if let Some(username) = &proxy.username {
+            p = p.basic_auth(username, proxy.password.as_deref().unwrap_or_default());
+        }
+        if !proxy.no_proxy.is_empty() {
+            p = p.no_proxy(reqwest::NoProxy::from_string(&proxy.no_proxy.join(",")));
+        }
+        builder = builder.proxy(p);
+    }
+
+    Ok(builder)
+}
+
+fn read_file(path: &std::path::Path) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn target(extra: serde_json::Value) -> Target {
+        let mut value = json!({ "url": "https://internal.example:8443/hook", "method": "POST" });
+        value.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn pool() -> ClientPool {
+        let policy = EgressPolicy::compile(&serde_json::from_value(json!({ "allow_private": true })).unwrap()).unwrap();
+        ClientPool::new(Duration::from_secs(5), policy).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_prepare_shares_clients_per_settings() {
+        let pool = pool();
+        let proxied = target(json!({ "proxy": { "url": "http://proxy.example:3128", "no_proxy": ["10.0.0.0/8"] } }));
+        let url = Url::parse(&proxied.url).unwrap();
+
+        pool.prepare(&proxied, url.clone()).await.unwrap();
+        pool.prepare(&proxied, url.clone()).await.unwrap();
+        pool.prepare(&target(json!({})), url).await.unwrap();
+        assert_eq!(pool.clients.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_prepare_server_name_override() {
+        let pool = pool();
+        let target = target(json!({ "url": "https://127.0.0.1:8443/hook", "tls": { "server_name": "receiver.internal" } }));
+        let prepared = pool
+            .prepare(&target, Url::parse(&target.url).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(prepared.url.as_str(), "https://receiver.internal:8443/hook");
+        assert_eq!(prepared.host_header.as_deref(), Some("127.0.0.1:8443"));
+    }
+
+    #[test]
+    fn test_validate_reports_bad_tls_settings() {
+        let pool = pool();
+        let err = pool
+            .validate(&target(json!({ "tls": { "ca_bundle": "/nonexistent/ca.pem" } })))
+            .unwrap_err();
+        assert!(err.contains("/nonexistent/ca.pem"));
+
+        let err = pool
+            .validate(&target(json!({ "tls": { "client_cert": "/tmp/cert.pem" } })))
+            .unwrap_err();
+        assert!(err.contains("together"));
+    }
+
+    #[tokio::test]
+    async fn test_prepare_enforces_egress_policy() {
+        let pool = ClientPool::new(Duration::from_secs(5), EgressPolicy::default()).unwrap();
+        let metadata = target(json!({ "url": "http://169.254.169.254/latest/meta-data/" }));
+        let err = pool
+            .prepare(&metadata, Url::parse(&metadata.url).unwrap())
+            .await
+            .unwrap_err();
+        assert!(err.contains("non-public"), "{}", err);
+
+        // Pinned addresses for a server name override are checked too
+        let pinned = target(json!({ "url": "https://localhost:8443/hook", "tls": { "server_name": "receiver.internal" } }));
+        assert!(pool.prepare(&pinned, Url::parse(&pinned.url).unwrap()).await.is_err());
+
+        // So are targets behind a proxy, which only the proxy connects to
+        let proxied = target(json!({ "url": "http://localhost:8080/hook", "proxy": { "url": "http://proxy.example:3128" } }));
+        let err = pool
+            .prepare(&proxied, Url::parse(&proxied.url).unwrap())
+            .await
+            .unwrap_err();
+        assert!(err.contains("non-public"), "{}", err);
+        let policy = EgressPolicy::compile(&serde_json::from_value(json!({ "allow_hosts": ["localhost"] })).unwrap()).unwrap();
+        let pool = ClientPool::new(Duration::from_secs(5), policy).unwrap();
+        pool.prepare(&proxied, Url::parse(&proxied.url).unwrap()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redirects_are_checked_against_egress_policy() {
+        use axum::response::Redirect;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = axum::Router::new()
+            .route("/metadata", axum::routing::get(|| async { Redirect::to("http://169.254.169.254/latest/meta-data/") }))
+            .route("/loopback", axum::routing::get(|| async { Redirect::to("http://[::1]:9/admin") }))
+            .route("/moved", axum::routing::get(|| async { Redirect::to("/hook") }))
+            .route("/hook", axum::routing::get(|| async { "ok" }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let policy = EgressPolicy::compile(&serde_json::from_value(json!({ "allow_hosts": ["127.0.0.1"] })).unwrap()).unwrap();
+        let pool = ClientPool::new(Duration::from_secs(5), policy).unwrap();
+        let client = pool.default_client();
+        for path in ["metadata", "loopback"] {
+            let err = client.get(format!("http://{}/{}", addr, path)).send().await.unwrap_err();
+            assert!(err.is_redirect(), "{}", err);
+            assert!(format!("{:?}", err).contains("non-public"), "{:?}", err);
+        }
+        let response = client.get(format!("http://{}/moved", addr)).send().await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+    }
+}
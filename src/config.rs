@@ -33,9 +33,49 @@ pub struct Args {
     #[arg(long, env = "HERMES_MAX_CONCURRENT_REQUESTS", default_value = "1000")]
     pub max_concurrent_requests: usize,
 
+    /// Maximum inbound request body size in bytes, compressed or decompressed
+    #[arg(long, env = "HERMES_MAX_BODY_BYTES", default_value = "2097152")]
+    pub max_body_bytes: usize,
+
+    /// Seconds a client has to send the request headers
+    #[arg(long, env = "HERMES_HEADER_READ_TIMEOUT", default_value = "10")]
+    pub header_read_timeout: u64,
+
+    /// Seconds a client has to send the request body
+    #[arg(long, env = "HERMES_BODY_READ_TIMEOUT", default_value = "30")]
+    pub body_read_timeout: u64,
+
     /// Health check endpoint
     #[arg(long, env = "HERMES_HEALTH_CHECK_ENABLED", default_value = "true")]
     pub health_check_enabled: bool,
+
+    /// OTLP/HTTP collector URL to export traces to; tracing export is off when unset
+    #[arg(long, env = "HERMES_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Service name reported with exported traces
+    #[arg(long, env = "HERMES_OTEL_SERVICE_NAME", default_value = "hermes-rs")]
+    pub otel_service_name: String,
+
+    /// TLS certificate chain (PEM) for the listener; enables HTTPS
+    #[arg(long, env = "HERMES_TLS_CERT", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// TLS private key (PEM) for the listener
+    #[arg(long, env = "HERMES_TLS_KEY", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// CA bundle (PEM) used to verify client certificates (mTLS)
+    #[arg(long, env = "HERMES_TLS_CLIENT_CA", requires = "tls_cert")]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// Also accept clients without a certificate when a client CA is set
+    #[arg(long, env = "HERMES_TLS_CLIENT_AUTH_OPTIONAL", default_value = "false")]
+    pub tls_client_auth_optional: bool,
+
+    /// Seconds between checks of the TLS files for changes (0 disables reload)
+    #[arg(long, env = "HERMES_TLS_RELOAD_INTERVAL", default_value = "30")]
+    pub tls_reload_interval: u64,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -47,12 +87,40 @@ pub struct Config {
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AppSettings {
+    /// Attempts per target request for registers without `retry_config`;
+    /// one by default, so such registers are not retried
     #[serde(default = "default_retry_attempts")]
     pub retry_attempts: u32,
+    /// Fixed delay between those attempts
     #[serde(default = "default_retry_delay_ms")]
     pub retry_delay_ms: u64,
     #[serde(default = "default_enable_metrics")]
     pub enable_metrics: bool,
+    /// CIDRs, addresses or `file:<path>` ranges allowed to send to endpoints
+    /// whose register has no `allowed_sources` of its own
+    #[serde(default)]
+    pub allowed_sources: Vec<String>,
+    /// Proxies whose `Forwarded`/`X-Forwarded-For` headers are believed
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    #[serde(default)]
+    pub egress: EgressConfig,
+    /// Header the request ID is forwarded to targets in; `null` stops forwarding it
+    #[serde(default = "default_request_id_header")]
+    pub request_id_header: Option<String>,
+    #[serde(default)]
+    pub delivery_log: Option<DeliveryLogConfig>,
+    /// The `/admin` API is only served when this is set
+    #[serde(default)]
+    pub admin: Option<AdminConfig>,
+    #[serde(default)]
+    pub redaction: RedactionConfig,
+    #[serde(default)]
+    pub debug: DebugConfig,
+    #[serde(default)]
+    pub readiness: ReadinessConfig,
+    #[serde(default)]
+    pub pause: PauseConfig,
 }
 
 impl Default for AppSettings {
@@ -61,18 +129,293 @@ impl Default for AppSettings {
             retry_attempts: default_retry_attempts(),
             retry_delay_ms: default_retry_delay_ms(),
             enable_metrics: default_enable_metrics(),
+            allowed_sources: Vec::new(),
+            trusted_proxies: Vec::new(),
+            egress: EgressConfig::default(),
+            request_id_header: default_request_id_header(),
+            delivery_log: None,
+            admin: None,
+            redaction: RedactionConfig::default(),
+            debug: DebugConfig::default(),
+            readiness: ReadinessConfig::default(),
+            pause: PauseConfig::default(),
+        }
+    }
+}
+
+/// Append-only JSONL record of every webhook that reached a register.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeliveryLogConfig {
+    pub path: PathBuf,
+    /// The log is rotated to `<path>.1` once it would grow past this size
+    #[serde(default = "default_delivery_log_max_file_bytes")]
+    pub max_file_bytes: u64,
+    /// Rotated files kept besides the current one
+    #[serde(default = "default_delivery_log_max_files")]
+    pub max_files: usize,
+    /// Records older than this are no longer returned, and rotated files
+    /// older than this are deleted
+    #[serde(default)]
+    pub max_age_hours: Option<u64>,
+    /// Also store inbound headers and bodies and the rendered body, not just
+    /// the rendered body's hash
+    #[serde(default)]
+    pub store_bodies: bool,
+}
+
+/// Values replaced with `[REDACTED]` in logs, debug output, delivery records
+/// and error responses.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct RedactionConfig {
+    /// Header names, in addition to `Authorization`, `Proxy-Authorization` and `Cookie`
+    #[serde(default)]
+    pub headers: Vec<String>,
+    /// Fields of JSON bodies, as `$.card.number` or `items[*].ssn`
+    #[serde(default)]
+    pub json_paths: Vec<String>,
+    /// Regular expressions matched against any text
+    #[serde(default)]
+    pub patterns: Vec<String>,
+}
+
+/// The `/debug` request bin.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DebugConfig {
+    /// Captured requests kept in memory; the oldest are dropped first
+    #[serde(default = "default_debug_capacity")]
+    pub capacity: usize,
+    /// JSONL file captured requests are appended to and reloaded from
+    #[serde(default)]
+    pub persist_path: Option<PathBuf>,
+    /// What `/debug` answers captured requests with
+    #[serde(default)]
+    pub response: DebugResponse,
+}
+
+impl Default for DebugConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default_debug_capacity(),
+            persist_path: None,
+            response: DebugResponse::default(),
+        }
+    }
+}
+
+/// A canned response, to test how registers handle slow or failing targets.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DebugResponse {
+    #[serde(default = "default_fixed_status")]
+    pub status: u16,
+    #[serde(default)]
+    pub delay_ms: u64,
+    #[serde(default)]
+    pub headers: std::collections::HashMap<String, String>,
+    /// Defaults to `{"status":"success","message":"Payload logged"}`
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+impl Default for DebugResponse {
+    fn default() -> Self {
+        Self {
+            status: default_fixed_status(),
+            delay_ms: 0,
+            headers: Default::default(),
+            body: None,
         }
     }
 }
 
+/// What `/ready` reports on besides configured target probes.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ReadinessConfig {
+    /// Not ready while this many delivery records wait to be written
+    #[serde(default = "default_queue_high_water")]
+    pub queue_high_water: usize,
+    /// On shutdown, report not ready for this long before connections stop
+    /// being accepted, so load balancers can move traffic away first
+    #[serde(default)]
+    pub drain_delay_seconds: u64,
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        Self {
+            queue_high_water: default_queue_high_water(),
+            drain_delay_seconds: 0,
+        }
+    }
+}
+
+/// What happens to webhooks for paused registers and targets.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PauseConfig {
+    #[serde(default)]
+    pub mode: PauseMode,
+    /// Status webhooks are rejected with, also once the buffer is full
+    #[serde(default = "default_pause_reject_status")]
+    pub reject_status: u16,
+    /// Requests buffered across all paused registers and targets
+    #[serde(default = "default_pause_buffer_capacity")]
+    pub buffer_capacity: usize,
+    /// JSONL file the buffer is kept in, so it survives a restart. Holds
+    /// rendered target URLs and headers, secrets included, and is created
+    /// with owner-only permissions
+    #[serde(default)]
+    pub buffer_path: Option<PathBuf>,
+}
+
+impl Default for PauseConfig {
+    fn default() -> Self {
+        Self {
+            mode: PauseMode::default(),
+            reject_status: default_pause_reject_status(),
+            buffer_capacity: default_pause_buffer_capacity(),
+            buffer_path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PauseMode {
+    /// Render and keep webhooks, sending them in order on resume
+    #[default]
+    Buffer,
+    /// Answer `reject_status` so senders retry later
+    Reject,
+}
+
+/// Settings for the `/admin` API on the listener.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AdminConfig {
+    pub auth: InboundAuth,
+    /// Serve the admin API on this address instead of the main listener,
+    /// with the same TLS settings and header read timeout
+    #[serde(default)]
+    pub bind_address: Option<String>,
+    /// Write register changes made through the admin API back to the
+    /// configuration file. Only its `registers` block is rewritten, so
+    /// comments within that block are lost
+    #[serde(default)]
+    pub persist: bool,
+}
+
+/// Where targets, token endpoints and proxies may be reached.
+///
+/// Non-public addresses (loopback, private, link-local, ...) are blocked
+/// unless `allow_private` is set or the host or address is explicitly allowed.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct EgressConfig {
+    #[serde(default)]
+    pub allow_private: bool,
+    /// Hosts (`name` or `*.domain`) allowed even if they resolve to non-public addresses
+    #[serde(default)]
+    pub allow_hosts: Vec<String>,
+    #[serde(default)]
+    pub deny_hosts: Vec<String>,
+    /// Ranges allowed even if they are non-public
+    #[serde(default)]
+    pub allow_cidrs: Vec<String>,
+    #[serde(default)]
+    pub deny_cidrs: Vec<String>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct WebhookRegister {
     pub endpoint: String,
     pub method: String,
     pub target: Target,
+    #[serde(default)]
     pub template: String,
     #[serde(default)]
     pub retry_config: Option<RetryConfig>,
+    #[serde(default)]
+    pub script: Option<ScriptConfig>,
+    #[serde(default)]
+    pub wasm: Option<WasmConfig>,
+    #[serde(default)]
+    pub auth: Option<InboundAuth>,
+    /// Sources allowed to send to this endpoint, in place of the global list
+    #[serde(default)]
+    pub allowed_sources: Vec<String>,
+    /// Overrides the global maximum body size for this endpoint
+    #[serde(default)]
+    pub max_body_bytes: Option<usize>,
+    #[serde(default)]
+    pub response: ResponsePolicy,
+    /// Answer the sender's verification handshake instead of forwarding it
+    #[serde(default)]
+    pub handshake: Option<Handshake>,
+    /// Periodically check that the target is reachable, for `/ready`
+    #[serde(default)]
+    pub probe: Option<TargetProbe>,
+    /// Disabled registers answer 404 until they are enabled again
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// A reachability check of a register's target. Any response below 500
+/// counts as reachable.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TargetProbe {
+    /// Defaults to the target URL, which must then not be a template
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default = "default_probe_method")]
+    pub method: String,
+    #[serde(default = "default_probe_interval_seconds")]
+    pub interval_seconds: u64,
+    #[serde(default = "default_probe_timeout_seconds")]
+    pub timeout_seconds: u64,
+    /// Report not ready while the target is unreachable
+    #[serde(default)]
+    pub required: bool,
+}
+
+/// Built-in subscription verification handshakes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Handshake {
+    /// Slack Events API `url_verification`: echo `challenge`
+    Slack,
+    /// Microsoft Graph subscription validation: return `validationToken` as text
+    Graph,
+    /// WebSub intent verification: return `hub.challenge` as text
+    Websub,
+}
+
+/// What the sender receives once the target request was sent.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ResponsePolicy {
+    /// Always 200 with `{"status":"success","target_response":...}`
+    #[default]
+    Wrap,
+    /// The target's status, headers and body
+    Passthrough,
+    /// Like `wrap`, but a non-2xx target status becomes 502 with details
+    MapErrors,
+    /// Status, headers and body rendered from the payload (`_raw`), the
+    /// inbound request (`_request`) and the target response (`_target`)
+    Template {
+        #[serde(default = "default_template_status")]
+        status: String,
+        #[serde(default)]
+        headers: std::collections::HashMap<String, String>,
+        #[serde(default)]
+        body: String,
+    },
+    /// A fixed response when the target answered 2xx, 502 otherwise
+    Fixed {
+        #[serde(default = "default_fixed_status")]
+        status: u16,
+        #[serde(default)]
+        headers: std::collections::HashMap<String, String>,
+        #[serde(default)]
+        body: String,
+    },
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -81,10 +424,154 @@ pub struct Target {
     pub method: String,
     #[serde(default)]
     pub headers: std::collections::HashMap<String, String>,
+    /// Query parameters appended to the URL; values are templates
+    #[serde(default)]
+    pub query: std::collections::HashMap<String, String>,
     #[serde(default)]
     pub timeout_seconds: Option<u64>,
+    #[serde(default)]
+    pub body_format: BodyFormat,
+    #[serde(default)]
+    pub auth: Option<TargetAuth>,
+    #[serde(default)]
+    pub tls: Option<TargetTls>,
+    #[serde(default)]
+    pub proxy: Option<TargetProxy>,
 }
 
+/// TLS settings for connections to a target.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct TargetTls {
+    /// PEM bundle of additional trusted CA certificates
+    #[serde(default)]
+    pub ca_bundle: Option<PathBuf>,
+    /// Only trust `ca_bundle`, not the built-in roots
+    #[serde(default)]
+    pub ca_bundle_only: bool,
+    /// PEM client certificate (chain) for mTLS
+    #[serde(default)]
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key for `client_cert`
+    #[serde(default)]
+    pub client_key: Option<PathBuf>,
+    /// Name used for SNI and certificate verification instead of the URL host
+    #[serde(default)]
+    pub server_name: Option<String>,
+    /// Minimum TLS version, "1.2" or "1.3"
+    #[serde(default)]
+    pub min_version: Option<String>,
+    /// Skip certificate verification; for development only
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+
+/// Outbound HTTP(S) proxy for a target.
+///
+/// The target host is still resolved and checked against `settings.egress`;
+/// hosts only the proxy can resolve must be listed in `allow_hosts`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TargetProxy {
+    pub url: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Hosts, domains or CIDRs that bypass the proxy
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+/// Credentials attached to every request sent to a target.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TargetAuth {
+    Basic {
+        username: String,
+        password: String,
+    },
+    Bearer {
+        token: String,
+    },
+    /// OAuth2 client-credentials grant; tokens are cached until shortly
+    /// before they expire
+    Oauth2(OAuth2Config),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OAuth2Config {
+    pub token_url: String,
+    pub client_id: String,
+    pub client_secret: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// Refresh the token this many seconds before it expires
+    #[serde(default = "default_oauth2_refresh_before_seconds")]
+    pub refresh_before_seconds: u64,
+}
+
+/// Credentials required from senders of a register's webhooks.
+///
+/// A request is accepted when it satisfies any of the configured schemes.
+/// Secrets are given in plain text or as `sha256:<hex digest>`.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct InboundAuth {
+    #[serde(default)]
+    pub api_key: Option<ApiKeyAuth>,
+    #[serde(default)]
+    pub bearer: Option<BearerAuth>,
+    #[serde(default)]
+    pub basic: Option<BasicAuth>,
+}
+
+/// API key sent in a header or a query parameter.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ApiKeyAuth {
+    #[serde(default)]
+    pub header: Option<String>,
+    #[serde(default)]
+    pub query: Option<String>,
+    pub keys: Vec<String>,
+}
+
+/// Bearer token sent in the `Authorization` header.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BearerAuth {
+    pub tokens: Vec<String>,
+}
+
+/// HTTP basic authentication.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BasicAuth {
+    pub credentials: Vec<BasicCredential>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BasicCredential {
+    pub username: String,
+    pub password: String,
+}
+
+/// How the rendered template is validated and sent to the target.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyFormat {
+    /// Rendered output must be valid JSON
+    #[default]
+    Json,
+    /// Rendered output is a flat JSON object or an already encoded query string
+    Form,
+    /// Rendered output is sent as plain text
+    Text,
+    /// Rendered output must be well-formed XML
+    Xml,
+    /// Rendered output is sent unchanged without validation
+    Raw,
+}
+
+/// How a register's target requests are retried, in place of
+/// `settings.retry_attempts` and `settings.retry_delay_ms`.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RetryConfig {
     pub attempts: u32,
@@ -92,14 +579,81 @@ pub struct RetryConfig {
     pub backoff_multiplier: f64,
 }
 
-fn default_retry_attempts() -> u32 { 3 }
+impl AppSettings {
+    /// The retries for registers without a `retry_config`.
+    pub fn default_retry(&self) -> RetryConfig {
+        RetryConfig {
+            attempts: self.retry_attempts,
+            delay_ms: self.retry_delay_ms,
+            backoff_multiplier: 1.0,
+        }
+    }
+}
+
+/// Optional Rhai script run before the template is rendered.
+///
+/// The script source is given either inline (`source`) or as a file (`path`).
+/// Secret references are not resolved in either, so `${...}` is Rhai's own
+/// string interpolation.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScriptConfig {
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default = "default_script_max_operations")]
+    pub max_operations: u64,
+    #[serde(default = "default_script_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+/// WebAssembly transform module that replaces template rendering.
+///
+/// See [`crate::wasm`] for the ABI the module must implement.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WasmConfig {
+    pub module: PathBuf,
+    #[serde(default = "default_wasm_fuel")]
+    pub fuel: u64,
+    #[serde(default = "default_wasm_max_memory_bytes")]
+    pub max_memory_bytes: usize,
+}
+
+fn default_retry_attempts() -> u32 { 1 }
+fn default_enabled() -> bool { true }
+fn default_fixed_status() -> u16 { 200 }
+fn default_template_status() -> String { "200".to_string() }
 fn default_retry_delay_ms() -> u64 { 1000 }
 fn default_enable_metrics() -> bool { false }
+fn default_request_id_header() -> Option<String> { Some("X-Request-Id".to_string()) }
+fn default_delivery_log_max_file_bytes() -> u64 { 10 * 1024 * 1024 }
+fn default_delivery_log_max_files() -> usize { 5 }
+fn default_debug_capacity() -> usize { 100 }
+fn default_queue_high_water() -> usize { 8_000 }
+fn default_pause_reject_status() -> u16 { 503 }
+fn default_pause_buffer_capacity() -> usize { 1_000 }
+fn default_probe_method() -> String { "HEAD".to_string() }
+fn default_probe_interval_seconds() -> u64 { 30 }
+fn default_probe_timeout_seconds() -> u64 { 5 }
+fn default_script_max_operations() -> u64 { 100_000 }
+fn default_script_timeout_ms() -> u64 { 50 }
+fn default_oauth2_refresh_before_seconds() -> u64 { 60 }
+fn default_wasm_fuel() -> u64 { 10_000_000 }
+fn default_wasm_max_memory_bytes() -> usize { 16 * 1024 * 1024 }
 
 impl Config {
     pub async fn load(path: &PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::load_with_source(path).await?.0)
+    }
+
+    /// Load the configuration, also returning the file as written, with
+    /// `${...}` references unresolved.
+    pub async fn load_with_source(path: &PathBuf) -> Result<(Self, serde_yaml::Value), Box<dyn std::error::Error>> {
         let content = tokio::fs::read_to_string(path).await?;
-        let config: Config = serde_yaml::from_str(&content)?;
-        Ok(config)
+        let source: serde_yaml::Value = serde_yaml::from_str(&content)?;
+        let mut value = source.clone();
+        crate::secrets::resolve(&mut value)?;
+        let config: Config = serde_yaml::from_value(value)?;
+        Ok((config, source))
     }
 }
\ No newline at end of file
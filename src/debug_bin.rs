@@ -0,0 +1,433 @@
+//! The `/debug` request bin.
+//!
+//! Requests sent to `/debug` are logged, kept in a bounded in-memory ring
+//! (optionally persisted to a JSONL file) and answered with a configurable
+//! canned response. Captures can be listed with `GET /debug/requests`,
+//! viewed with `GET /debug/requests/{id}` and followed live as Server-Sent
+//! Events from `GET /debug/stream`. Those routes are part of the admin API,
+//! so they need its credentials and are served where it is.
+//! Headers and bodies are redacted before they are stored.
+
+use crate::config::{DebugConfig, DebugResponse};
+use crate::redaction;
+use axum::{
+    body::Bytes,
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{any, get},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::Infallible;
+use std::io::{BufRead, BufReader, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedRequest {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    pub source: String,
+    pub method: String,
+    pub uri: String,
+    pub headers: BTreeMap<String, String>,
+    pub body_bytes: usize,
+    pub body: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CaptureSummary<'a> {
+    id: u64,
+    timestamp: DateTime<Utc>,
+    method: &'a str,
+    uri: &'a str,
+    body_bytes: usize,
+}
+
+struct Ring {
+    next_id: u64,
+    requests: VecDeque<CapturedRequest>,
+}
+
+/// Appends captures to `persist_path` on its own thread, rewriting the file
+/// with only the kept captures once it holds twice as many lines.
+struct Persister {
+    path: PathBuf,
+    capacity: usize,
+    kept: VecDeque<CapturedRequest>,
+    lines: usize,
+}
+
+impl Persister {
+    fn write(&mut self, request: CapturedRequest) -> std::io::Result<()> {
+        if self.kept.len() >= self.capacity {
+            self.kept.pop_front();
+        }
+        self.kept.push_back(request);
+
+        if self.lines + 1 >= self.capacity.max(1) * 2 {
+            self.compact()
+        } else {
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&self.path)?;
+            writeln!(file, "{}", serde_json::to_string(self.kept.back().unwrap()).unwrap_or_default())?;
+            self.lines += 1;
+            Ok(())
+        }
+    }
+
+    fn compact(&mut self) -> std::io::Result<()> {
+        let kept: String = self
+            .kept
+            .iter()
+            .filter_map(|r| serde_json::to_string(r).ok())
+            .map(|line| line + "\n")
+            .collect();
+        let mut temp = self.path.as_os_str().to_owned();
+        temp.push(".tmp");
+        std::fs::write(&temp, kept)?;
+        std::fs::rename(&temp, &self.path)?;
+        self.lines = self.kept.len();
+        Ok(())
+    }
+
+    /// Start the writer thread.
+    fn spawn(mut self) -> Result<SyncSender<CapturedRequest>, String> {
+        let (sender, receiver) = mpsc::sync_channel::<CapturedRequest>(256);
+        std::thread::Builder::new()
+            .name("debug-bin".to_string())
+            .spawn(move || {
+                for request in receiver {
+                    if let Err(e) = self.write(request) {
+                        warn!(path = %self.path.display(), error = %e, "Failed to persist debug capture");
+                    }
+                }
+            })
+            .map_err(|e| format!("Failed to start debug bin writer: {}", e))?;
+        Ok(sender)
+    }
+}
+
+pub struct DebugBin {
+    config: DebugConfig,
+    status: StatusCode,
+    headers: HeaderMap,
+    ring: Mutex<Ring>,
+    live: broadcast::Sender<CapturedRequest>,
+    persister: Option<SyncSender<CapturedRequest>>,
+}
+
+/// Check the canned response's status and headers.
+pub fn canned_response(response: &DebugResponse) -> Result<(StatusCode, HeaderMap), String> {
+    let status = StatusCode::from_u16(response.status)
+        .map_err(|_| format!("Invalid debug response status {}", response.status))?;
+    let mut headers = HeaderMap::new();
+    for (name, value) in &response.headers {
+        headers.insert(
+            HeaderName::from_bytes(name.as_bytes()).map_err(|e| format!("Invalid debug response header {}: {}", name, e))?,
+            HeaderValue::from_str(value).map_err(|e| format!("Invalid debug response header value for {}: {}", name, e))?,
+        );
+    }
+    Ok((status, headers))
+}
+
+impl DebugBin {
+    /// Reload persisted captures, compacting the file to those kept.
+    pub fn new(config: &DebugConfig) -> Result<Self, String> {
+        let (status, headers) = canned_response(&config.response)?;
+
+        let mut requests = VecDeque::with_capacity(config.capacity);
+        let mut persister = None;
+        if let Some(path) = &config.persist_path {
+            match std::fs::File::open(path) {
+                Ok(file) => {
+                    for line in BufReader::new(file).lines().map_while(Result::ok) {
+                        if let Ok(request) = serde_json::from_str::<CapturedRequest>(&line) {
+                            if requests.len() == config.capacity {
+                                requests.pop_front();
+                            }
+                            requests.push_back(request);
+                        }
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+            }
+            let mut writer = Persister {
+                path: path.clone(),
+                capacity: config.capacity,
+                kept: requests.clone(),
+                lines: 0,
+            };
+            writer.compact().map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            persister = Some(writer.spawn()?);
+        }
+        let next_id = requests.back().map(|r: &CapturedRequest| r.id + 1).unwrap_or(1);
+
+        Ok(Self {
+            config: config.clone(),
+            status,
+            headers,
+            ring: Mutex::new(Ring { next_id, requests }),
+            live: broadcast::channel(64).0,
+            persister,
+        })
+    }
+
+    fn capture(&self, source: SocketAddr, method: &Method, uri: &Uri, headers: &HeaderMap, body: &[u8]) -> CapturedRequest {
+        let redactor = redaction::current();
+        let mut ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+        let request = CapturedRequest {
+            id: ring.next_id,
+            timestamp: Utc::now(),
+            source: source.ip().to_string(),
+            method: method.to_string(),
+            uri: uri.to_string(),
+            headers: redactor.headers(headers.iter().filter_map(|(k, v)| Some((k.as_str(), v.to_str().ok()?)))),
+            body_bytes: body.len(),
+            body: redactor.body(&String::from_utf8_lossy(body)),
+        };
+        ring.next_id += 1;
+        if self.config.capacity > 0 {
+            if ring.requests.len() >= self.config.capacity {
+                ring.requests.pop_front();
+            }
+            ring.requests.push_back(request.clone());
+        }
+
+        drop(ring);
+
+        // Written by the persister thread, off the async runtime
+        if let Some(persister) = &self.persister {
+            if let Err(TrySendError::Full(_)) = persister.try_send(request.clone()) {
+                warn!("Debug bin writer is behind, not persisting capture");
+            }
+        }
+        request
+    }
+
+    fn get(&self, id: u64) -> Option<CapturedRequest> {
+        let ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+        ring.requests.iter().find(|r| r.id == id).cloned()
+    }
+}
+
+/// The public `/debug` capture route.
+pub fn router<S: Clone + Send + Sync + 'static>(bin: Arc<DebugBin>) -> Router<S> {
+    Router::new().route("/debug", any(capture)).with_state(bin)
+}
+
+/// The routes reading captures, served by the admin API behind its auth.
+pub fn admin_router<S: Clone + Send + Sync + 'static>(bin: Arc<DebugBin>) -> Router<S> {
+    Router::new()
+        .route("/debug/requests", get(list_requests))
+        .route("/debug/requests/:id", get(get_request))
+        .route("/debug/stream", get(stream_requests))
+        .with_state(bin)
+}
+
+async fn capture(
+    State(bin): State<Arc<DebugBin>>,
+    ConnectInfo(source): ConnectInfo<SocketAddr>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let request = bin.capture(source, &method, &uri, &headers, &body);
+    info!("Debug request headers: {:?}", request.headers);
+    info!("Debug request payload: {}", request.body);
+    let _ = bin.live.send(request);
+
+    let response = &bin.config.response;
+    if response.delay_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(response.delay_ms)).await;
+    }
+    match &response.body {
+        Some(body) => (bin.status, bin.headers.clone(), body.clone()).into_response(),
+        None => (
+            bin.status,
+            bin.headers.clone(),
+            Json(json!({"status": "success", "message": "Payload logged"})),
+        )
+            .into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+    limit: Option<usize>,
+}
+
+/// Captured requests, newest first, without headers and bodies.
+async fn list_requests(State(bin): State<Arc<DebugBin>>, Query(query): Query<ListQuery>) -> Json<serde_json::Value> {
+    let ring = bin.ring.lock().unwrap_or_else(|e| e.into_inner());
+    let requests: Vec<_> = ring
+        .requests
+        .iter()
+        .rev()
+        .take(query.limit.unwrap_or(usize::MAX))
+        .map(|r| CaptureSummary {
+            id: r.id,
+            timestamp: r.timestamp,
+            method: &r.method,
+            uri: &r.uri,
+            body_bytes: r.body_bytes,
+        })
+        .collect();
+    Json(json!({ "requests": requests }))
+}
+
+async fn get_request(State(bin): State<Arc<DebugBin>>, Path(id): Path<u64>) -> Response {
+    match bin.get(id) {
+        Some(request) => Json(request).into_response(),
+        None => (StatusCode::NOT_FOUND, Json(json!({ "error": "Captured request not found" }))).into_response(),
+    }
+}
+
+/// Every request captured from now on, as a `request` event.
+async fn stream_requests(State(bin): State<Arc<DebugBin>>) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = bin.live.subscribe();
+    let stream = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(request) => {
+                    let event = Event::default().event("request").json_data(&request).unwrap_or_default();
+                    return Some((Ok(event), receiver));
+                }
+                // A slow client misses captures rather than holding them up
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn serve(config: DebugConfig) -> String {
+        let bin = Arc::new(DebugBin::new(&config).unwrap());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = router::<()>(bin.clone())
+            .merge(admin_router(bin))
+            .into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_capture_list_view_and_canned_response() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = DebugConfig {
+            capacity: 2,
+            persist_path: Some(dir.path().join("debug.jsonl")),
+            response: DebugResponse {
+                status: 503,
+                body: Some("try later".to_string()),
+                ..Default::default()
+            },
+        };
+        let base = serve(config.clone()).await;
+        let client = reqwest::Client::new();
+
+        for n in 1..=3 {
+            let response = client.post(format!("{}/debug?n={}", base, n)).body(format!("{{\"n\":{}}}", n)).send().await.unwrap();
+            assert_eq!(response.status(), 503);
+            assert_eq!(response.text().await.unwrap(), "try later");
+        }
+
+        let list: serde_json::Value = client.get(format!("{}/debug/requests", base)).send().await.unwrap().json().await.unwrap();
+        let ids: Vec<_> = list["requests"].as_array().unwrap().iter().map(|r| r["id"].as_u64().unwrap()).collect();
+        assert_eq!(ids, [3, 2]);
+
+        let request: serde_json::Value = client.get(format!("{}/debug/requests/2", base)).send().await.unwrap().json().await.unwrap();
+        assert_eq!(request["uri"], "/debug?n=2");
+        assert_eq!(request["body"], r#"{"n":2}"#);
+        let missing = client.get(format!("{}/debug/requests/1", base)).send().await.unwrap();
+        assert_eq!(missing.status(), 404);
+
+        // Persisted captures are reloaded, and ids carry on from them, once
+        // the writer thread caught up
+        let path = config.persist_path.clone().unwrap();
+        for _ in 0..100 {
+            if std::fs::read_to_string(&path).unwrap_or_default().contains(r#""id":3"#) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let bin = DebugBin::new(&config).unwrap();
+        assert_eq!(bin.get(3).unwrap().body, r#"{"n":3}"#);
+        assert_eq!(bin.ring.lock().unwrap().next_id, 4);
+    }
+
+    #[test]
+    fn test_persisted_file_stays_bounded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("debug.jsonl");
+        let mut persister = Persister {
+            path: path.clone(),
+            capacity: 3,
+            kept: VecDeque::new(),
+            lines: 0,
+        };
+        for id in 1..=20 {
+            persister
+                .write(CapturedRequest {
+                    id,
+                    timestamp: Utc::now(),
+                    source: "127.0.0.1".to_string(),
+                    method: "POST".to_string(),
+                    uri: "/debug".to_string(),
+                    headers: BTreeMap::new(),
+                    body_bytes: 0,
+                    body: String::new(),
+                })
+                .unwrap();
+            let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+            assert!(lines < 6, "{} lines after {} captures", lines, id);
+        }
+
+        // The newest captures are the ones reloaded
+        let bin = DebugBin::new(&DebugConfig {
+            capacity: 3,
+            persist_path: Some(path),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(bin.get(20).is_some() && bin.get(18).is_some() && bin.get(17).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_sends_captures() {
+        let base = serve(DebugConfig::default()).await;
+        let client = reqwest::Client::new();
+        let mut stream = client.get(format!("{}/debug/stream", base)).send().await.unwrap();
+
+        // The subscription is in place once the response headers arrived
+        client.post(format!("{}/debug", base)).body("ping").send().await.unwrap();
+
+        let mut received = String::new();
+        while !received.contains("\n\n") {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), stream.chunk()).await.unwrap().unwrap().unwrap();
+            received.push_str(&String::from_utf8_lossy(&chunk));
+        }
+        assert!(received.starts_with("event: request\n"), "{}", received);
+        assert!(received.contains(r#""body":"ping""#), "{}", received);
+    }
+}
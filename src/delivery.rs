@@ -0,0 +1,164 @@
+use crate::config::{RetryConfig, TargetAuth};
+use crate::target_auth::{SendError, TokenCache};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use std::time::Duration;
+use tracing::{info_span, warn, Instrument};
+
+/// Whether a failed attempt is worth retrying.
+///
+/// Requests that cannot have reached the target, and 429 and 503 answers,
+/// are retried for every method. Other errors and 5xx answers may come after
+/// the target acted on the request, so they are only retried for idempotent
+/// methods: retrying a POST there could deliver it twice.
+fn should_retry(method: &Method, result: &Result<Response, SendError>) -> bool {
+    let idempotent = method.is_idempotent();
+    match result {
+        Ok(response) => match response.status() {
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => true,
+            status => status.is_server_error() && idempotent,
+        },
+        Err(SendError::NotSent(_)) => true,
+        Err(SendError::Failed(_)) => idempotent,
+    }
+}
+
+/// Send a target request, retrying per `retry`: the register's
+/// `retry_config`, or [`crate::config::AppSettings::default_retry`].
+///
+/// Every attempt after the first runs in a `webhook.retry` span. Returns the
+/// last attempt's result with the number of attempts made.
+pub async fn send_with_retries(
+    token_cache: &TokenCache,
+    auth: Option<&TargetAuth>,
+    retry: &RetryConfig,
+    method: &Method,
+    request: RequestBuilder,
+) -> (Result<Response, String>, u32) {
+    let attempts = retry.attempts.max(1);
+    let mut delay = Duration::from_millis(retry.delay_ms);
+
+    let mut attempt = 1;
+    loop {
+        let Some(builder) = request.try_clone() else {
+            // Streaming bodies cannot be replayed
+            return (token_cache.send(auth, request).await, attempt);
+        };
+
+        let result = if attempt == 1 {
+            token_cache.try_send(auth, builder).await
+        } else {
+            token_cache
+                .try_send(auth, builder)
+                .instrument(info_span!("webhook.retry", attempt))
+                .await
+        };
+
+        if attempt >= attempts || !should_retry(method, &result) {
+            return (result.map_err(|e| e.to_string()), attempt);
+        }
+
+        match &result {
+            Ok(response) => warn!(attempt, status = %response.status(), "Target request failed, retrying"),
+            Err(e) => warn!(attempt, error = %e, "Target request failed, retrying"),
+        }
+        tokio::time::sleep(delay).await;
+        delay = delay.mul_f64(retry.backoff_multiplier.max(1.0));
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode as AxumStatus, routing::any, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    async fn flaky_target(failures: usize, status: AxumStatus) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/hook",
+            any(move || {
+                let counter = counter.clone();
+                async move {
+                    match counter.fetch_add(1, Ordering::SeqCst) < failures {
+                        true => status,
+                        false => AxumStatus::OK,
+                    }
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/hook", addr), hits)
+    }
+
+    fn retry(attempts: u32) -> RetryConfig {
+        RetryConfig {
+            attempts,
+            delay_ms: 1,
+            backoff_multiplier: 2.0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success() {
+        let (url, hits) = flaky_target(2, AxumStatus::SERVICE_UNAVAILABLE).await;
+        let client = reqwest::Client::new();
+        let cache = TokenCache::new(client.clone());
+
+        let (response, attempts) = send_with_retries(&cache, None, &retry(3), &Method::POST, client.post(&url).body("{}")).await;
+        assert_eq!(response.unwrap().status(), StatusCode::OK);
+        assert_eq!(attempts, 3);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_attempts() {
+        let (url, hits) = flaky_target(5, AxumStatus::SERVICE_UNAVAILABLE).await;
+        let client = reqwest::Client::new();
+        let cache = TokenCache::new(client.clone());
+
+        let (response, attempts) = send_with_retries(&cache, None, &retry(2), &Method::POST, client.post(&url).body("{}")).await;
+        assert_eq!(response.unwrap().status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(attempts, 2);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_only_idempotent_methods_retry_ambiguous_failures() {
+        let (url, hits) = flaky_target(5, AxumStatus::BAD_GATEWAY).await;
+        let client = reqwest::Client::new();
+        let cache = TokenCache::new(client.clone());
+
+        // The target may have acted on a POST answered 502, so it is not resent
+        let (response, attempts) = send_with_retries(&cache, None, &retry(3), &Method::POST, client.post(&url)).await;
+        assert_eq!(response.unwrap().status(), StatusCode::BAD_GATEWAY);
+        assert_eq!((attempts, hits.load(Ordering::SeqCst)), (1, 1));
+
+        let (_, attempts) = send_with_retries(&cache, None, &retry(3), &Method::PUT, client.put(&url)).await;
+        assert_eq!((attempts, hits.load(Ordering::SeqCst)), (3, 4));
+
+        // Nothing listens here, so the request never reached a target
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = format!("http://{}/hook", listener.local_addr().unwrap());
+        drop(listener);
+        let (response, attempts) = send_with_retries(&cache, None, &retry(2), &Method::POST, client.post(&closed)).await;
+        assert!(response.is_err());
+        assert_eq!(attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_default_settings_send_once() {
+        let (url, hits) = flaky_target(1, AxumStatus::SERVICE_UNAVAILABLE).await;
+        let client = reqwest::Client::new();
+        let cache = TokenCache::new(client.clone());
+
+        let default_retry = crate::config::AppSettings::default().default_retry();
+        let (response, attempts) = send_with_retries(&cache, None, &default_retry, &Method::POST, client.post(&url)).await;
+        assert_eq!(response.unwrap().status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!((attempts, hits.load(Ordering::SeqCst)), (1, 1));
+    }
+}
@@ -0,0 +1,543 @@
+//! Append-only JSONL log of the webhooks that reached a register.
+//!
+//! Records are written by a background thread, so a slow disk never holds up
+//! a webhook. The current file is rotated to `<path>.1`, `<path>.2`, ... once
+//! it would grow past `max_file_bytes`; at most `max_files` rotated files are
+//! kept, and with `max_age_hours` set, older rotated files are deleted and
+//! older records are no longer returned. Records can hold request and
+//! response bodies, so the files are created readable by the owner only.
+
+use crate::config::DeliveryLogConfig;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tracing::warn;
+
+/// Records waiting to be written before new ones are dropped.
+pub const QUEUE_CAPACITY: usize = 10_000;
+
+/// How often rotated files are checked against `max_age_hours`.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Records returned by a query unless it asks for fewer.
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The target answered 2xx
+    Delivered,
+    /// Rendering or sending failed, or the target answered non-2xx
+    Failed,
+    /// A script or wasm transform dropped the event
+    Dropped,
+    /// Held while the register or target was paused; sending it is recorded
+    /// separately
+    Buffered,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryRecord {
+    pub request_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub endpoint: String,
+    pub outcome: Outcome,
+    pub inbound: InboundRecord,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<TargetRecord>,
+    /// The target's response status
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Time spent on the target request, retries included
+    #[serde(default)]
+    pub latency_ms: u64,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rendered_sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rendered_body: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Redaction changed the stored inbound body, so it cannot be replayed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub body_redacted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboundRecord {
+    pub method: String,
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    pub body_bytes: usize,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetRecord {
+    pub method: String,
+    pub url: String,
+}
+
+impl DeliveryRecord {
+    pub fn new(request_id: &str, timestamp: DateTime<Utc>, endpoint: &str, inbound: InboundRecord) -> Self {
+        Self {
+            request_id: request_id.to_string(),
+            timestamp,
+            endpoint: endpoint.to_string(),
+            outcome: Outcome::Failed,
+            inbound,
+            target: None,
+            status: None,
+            latency_ms: 0,
+            attempts: 0,
+            rendered_sha256: None,
+            rendered_body: None,
+            error: None,
+            body_redacted: false,
+        }
+    }
+
+    /// Settle the outcome once the request has been handled, with the error
+    /// returned to the sender, if any.
+    pub fn finish(&mut self, error: Option<String>) {
+        self.error = error;
+        if !matches!(self.outcome, Outcome::Dropped | Outcome::Buffered) {
+            let delivered = self.error.is_none() && self.status.is_some_and(|s| (200..300).contains(&s));
+            self.outcome = if delivered { Outcome::Delivered } else { Outcome::Failed };
+        }
+    }
+}
+
+/// Matches records by target status code (`502`), status class (`5xx`) or
+/// outcome (`delivered`, `failed`, `dropped`, `buffered`).
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum StatusFilter {
+    Code(u16),
+    Class(u16),
+    Outcome(Outcome),
+}
+
+impl FromStr for StatusFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        match s.as_str() {
+            "delivered" => return Ok(Self::Outcome(Outcome::Delivered)),
+            "failed" => return Ok(Self::Outcome(Outcome::Failed)),
+            "dropped" => return Ok(Self::Outcome(Outcome::Dropped)),
+            "buffered" => return Ok(Self::Outcome(Outcome::Buffered)),
+            _ => {}
+        }
+        if let Some(class) = s.strip_suffix("xx").and_then(|c| c.parse::<u16>().ok()).filter(|c| (1..=5).contains(c)) {
+            return Ok(Self::Class(class));
+        }
+        s.parse::<u16>()
+            .ok()
+            .filter(|code| (100..600).contains(code))
+            .map(Self::Code)
+            .ok_or_else(|| format!("invalid status filter '{}'", s))
+    }
+}
+
+impl TryFrom<String> for StatusFilter {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl StatusFilter {
+    fn matches(&self, record: &DeliveryRecord) -> bool {
+        match self {
+            Self::Code(code) => record.status == Some(*code),
+            Self::Class(class) => record.status.is_some_and(|s| s / 100 == *class),
+            Self::Outcome(outcome) => record.outcome == *outcome,
+        }
+    }
+}
+
+/// Query parameters of `GET /admin/deliveries`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeliveryFilter {
+    pub endpoint: Option<String>,
+    pub status: Option<StatusFilter>,
+    /// RFC 3339 timestamps bounding the records' receive time
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl DeliveryFilter {
+    fn matches(&self, record: &DeliveryRecord) -> bool {
+        self.endpoint.as_ref().is_none_or(|e| *e == record.endpoint)
+            && self.status.as_ref().is_none_or(|s| s.matches(record))
+            && self.since.is_none_or(|since| record.timestamp >= since)
+            && self.until.is_none_or(|until| record.timestamp <= until)
+    }
+}
+
+enum Message {
+    Record(Box<DeliveryRecord>),
+    Flush(SyncSender<()>),
+}
+
+/// How far the writer thread is behind, for readiness.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueState {
+    pub pending: usize,
+    /// The last write failed with this error
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+struct Progress {
+    pending: AtomicUsize,
+    error: Mutex<Option<String>>,
+}
+
+/// Handle to the delivery log and its writer thread.
+pub struct DeliveryLog {
+    config: DeliveryLogConfig,
+    sender: SyncSender<Message>,
+    progress: Arc<Progress>,
+}
+
+impl DeliveryLog {
+    /// Open (or create) the log and start its writer thread.
+    pub fn open(config: &DeliveryLogConfig) -> Result<Self, String> {
+        let mut writer = Writer::open(config)
+            .map_err(|e| format!("Failed to open delivery log {}: {}", config.path.display(), e))?;
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let progress = Arc::new(Progress::default());
+
+        let writer_progress = progress.clone();
+        std::thread::Builder::new()
+            .name("delivery-log".to_string())
+            .spawn(move || loop {
+                match receiver.recv_timeout(PRUNE_INTERVAL) {
+                    Ok(Message::Record(record)) => {
+                        let result = writer.write(&record);
+                        writer_progress.pending.fetch_sub(1, Ordering::Relaxed);
+                        let mut error = writer_progress.error.lock().unwrap_or_else(|e| e.into_inner());
+                        match result {
+                            Ok(()) => *error = None,
+                            Err(e) => {
+                                warn!(error = %e, "Failed to write delivery record");
+                                *error = Some(e.to_string());
+                            }
+                        }
+                    }
+                    // Records are written unbuffered, so everything sent
+                    // before the flush is on disk by now
+                    Ok(Message::Flush(done)) => {
+                        let _ = done.send(());
+                    }
+                    Err(RecvTimeoutError::Timeout) => writer.prune(),
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            })
+            .map_err(|e| format!("Failed to start delivery log writer: {}", e))?;
+
+        Ok(Self {
+            config: config.clone(),
+            sender,
+            progress,
+        })
+    }
+
+    /// Queue a record for writing. Bodies and headers are dropped unless
+    /// `store_bodies` is set, and redacted if they are.
+    pub fn record(&self, mut record: DeliveryRecord) {
+        if self.config.store_bodies {
+            let redactor = crate::redaction::current();
+            record.inbound.headers = redactor.headers(record.inbound.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+            if let Some(body) = &record.inbound.body {
+                let (redacted, changed) = redactor.body_checked(body);
+                record.body_redacted |= changed;
+                record.inbound.body = Some(redacted);
+            }
+            record.rendered_body = record.rendered_body.map(|body| redactor.body(&body));
+        } else {
+            record.inbound.headers.clear();
+            record.inbound.body = None;
+            record.rendered_body = None;
+        }
+        record.error = record.error.map(|e| crate::secrets::redact(&e));
+        // Counted before sending, so the writer never decrements first
+        self.progress.pending.fetch_add(1, Ordering::Relaxed);
+        let dropped = match self.sender.try_send(Message::Record(Box::new(record))) {
+            Ok(()) => return,
+            Err(TrySendError::Full(_)) => "Delivery log queue is full, dropping record",
+            Err(TrySendError::Disconnected(_)) => {
+                *self.progress.error.lock().unwrap_or_else(|e| e.into_inner()) = Some("writer stopped".to_string());
+                "Delivery log writer stopped, dropping record"
+            }
+        };
+        self.progress.pending.fetch_sub(1, Ordering::Relaxed);
+        warn!("{}", dropped);
+    }
+
+    /// Records waiting to be written, and the last write error.
+    pub fn queue_state(&self) -> QueueState {
+        QueueState {
+            pending: self.progress.pending.load(Ordering::Relaxed),
+            error: self.progress.error.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+        }
+    }
+
+    /// Wait until every record queued so far has been written.
+    pub fn flush(&self) {
+        let (done, wait) = mpsc::sync_channel(1);
+        if self.sender.send(Message::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+
+    /// The newest records matching `filter`, newest first. Reads the log
+    /// files, so call it from a blocking context.
+    pub fn query(&self, filter: &DeliveryFilter) -> Result<Vec<DeliveryRecord>, String> {
+        let limit = filter.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+        let cutoff = self
+            .config
+            .max_age_hours
+            .map(|hours| Utc::now() - chrono::Duration::hours(hours as i64));
+
+        // Oldest file first, so the newest matches end up at the back
+        let mut matches = VecDeque::with_capacity(limit);
+        for path in log_files(&self.config) {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+            };
+            for line in BufReader::new(file).lines() {
+                let line = line.map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                // Skip lines cut short by a crash rather than failing the query
+                let Ok(record) = serde_json::from_str::<DeliveryRecord>(&line) else {
+                    continue;
+                };
+                if cutoff.is_some_and(|cutoff| record.timestamp < cutoff) || !filter.matches(&record) {
+                    continue;
+                }
+                if matches.len() == limit {
+                    matches.pop_front();
+                }
+                matches.push_back(record);
+            }
+        }
+        Ok(matches.into_iter().rev().collect())
+    }
+}
+
+/// `<path>.<index>`, the `index`th rotated file.
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// All log files, oldest first.
+pub(crate) fn log_files(config: &DeliveryLogConfig) -> Vec<PathBuf> {
+    (1..=config.max_files)
+        .rev()
+        .map(|i| rotated_path(&config.path, i))
+        .chain(std::iter::once(config.path.clone()))
+        .collect()
+}
+
+/// Open a log file for appending, creating it mode 0600. Rotated files are
+/// renamed from it and keep that mode.
+fn open_log(path: &Path) -> std::io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+struct Writer {
+    config: DeliveryLogConfig,
+    file: File,
+    size: u64,
+}
+
+impl Writer {
+    fn open(config: &DeliveryLogConfig) -> std::io::Result<Self> {
+        if let Some(parent) = config.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = open_log(&config.path)?;
+        // Tighten files written before they were created owner-only
+        #[cfg(unix)]
+        for path in log_files(config).iter().filter(|path| path.exists()) {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+        let size = file.metadata()?.len();
+        Ok(Self {
+            config: config.clone(),
+            file,
+            size,
+        })
+    }
+
+    fn write(&mut self, record: &DeliveryRecord) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        if self.size > 0 && self.size + line.len() as u64 > self.config.max_file_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(&line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> std::io::Result<()> {
+        let path = &self.config.path;
+        if self.config.max_files == 0 {
+            std::fs::remove_file(path)?;
+        } else {
+            for i in (1..self.config.max_files).rev() {
+                let from = rotated_path(path, i);
+                if from.exists() {
+                    std::fs::rename(from, rotated_path(path, i + 1))?;
+                }
+            }
+            std::fs::rename(path, rotated_path(path, 1))?;
+        }
+        self.file = open_log(path)?;
+        self.size = 0;
+        self.prune();
+        Ok(())
+    }
+
+    /// Delete rotated files last written before `max_age_hours`.
+    fn prune(&self) {
+        let Some(hours) = self.config.max_age_hours else {
+            return;
+        };
+        let Some(cutoff) = SystemTime::now().checked_sub(Duration::from_secs(hours * 3600)) else {
+            return;
+        };
+        for i in 1..=self.config.max_files {
+            let path = rotated_path(&self.config.path, i);
+            let expired = std::fs::metadata(&path)
+                .and_then(|m| m.modified())
+                .is_ok_and(|modified| modified < cutoff);
+            if expired {
+                if let Err(e) = std::fs::remove_file(&path) {
+                    warn!(path = %path.display(), error = %e, "Failed to delete expired delivery log");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(dir: &Path, max_file_bytes: u64) -> DeliveryLogConfig {
+        DeliveryLogConfig {
+            path: dir.join("deliveries.jsonl"),
+            max_file_bytes,
+            max_files: 2,
+            max_age_hours: None,
+            store_bodies: false,
+        }
+    }
+
+    fn record(id: usize, endpoint: &str, status: Option<u16>) -> DeliveryRecord {
+        let inbound = InboundRecord {
+            method: "POST".to_string(),
+            source: "127.0.0.1".to_string(),
+            content_type: Some("application/json".to_string()),
+            body_bytes: 2,
+            headers: BTreeMap::from([("x-github-event".to_string(), "push".to_string())]),
+            body: Some("{}".to_string()),
+        };
+        let timestamp = DateTime::from_timestamp(1_700_000_000 + id as i64 * 60, 0).unwrap();
+        let mut record = DeliveryRecord::new(&format!("req-{}", id), timestamp, endpoint, inbound);
+        record.status = status;
+        record.finish(status.is_none().then(|| "Failed to send request to target".to_string()));
+        record
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let log = DeliveryLog::open(&config(dir.path(), 1024 * 1024)).unwrap();
+        log.record(record(0, "/github", Some(200)));
+        log.record(record(1, "/github", Some(502)));
+        log.record(record(2, "/alerts", None));
+        log.record(record(3, "/github", Some(201)));
+        log.flush();
+
+        let ids = |filter: DeliveryFilter| -> Vec<String> {
+            log.query(&filter).unwrap().into_iter().map(|r| r.request_id).collect()
+        };
+        assert_eq!(ids(DeliveryFilter::default()), ["req-3", "req-2", "req-1", "req-0"]);
+        let github = DeliveryFilter {
+            endpoint: Some("/github".to_string()),
+            status: Some("2xx".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(ids(github), ["req-3", "req-0"]);
+        let failed = DeliveryFilter {
+            status: Some("failed".parse().unwrap()),
+            since: Some(DateTime::from_timestamp(1_700_000_060, 0).unwrap()),
+            until: Some(DateTime::from_timestamp(1_700_000_120, 0).unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(ids(failed), ["req-2", "req-1"]);
+        assert_eq!(ids(DeliveryFilter { limit: Some(1), ..Default::default() }), ["req-3"]);
+        assert!("7xx".parse::<StatusFilter>().is_err());
+
+        // Bodies are only kept with store_bodies
+        let stored = &log.query(&DeliveryFilter::default()).unwrap()[0];
+        assert!(stored.inbound.body.is_none() && stored.inbound.headers.is_empty());
+        assert_eq!(stored.outcome, Outcome::Delivered);
+    }
+
+    #[test]
+    fn test_rotation_keeps_max_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = config(dir.path(), 400);
+        let log = DeliveryLog::open(&config).unwrap();
+        for id in 0..20 {
+            log.record(record(id, "/github", Some(200)));
+        }
+        log.flush();
+
+        assert!(rotated_path(&config.path, 2).exists());
+        assert!(!rotated_path(&config.path, 3).exists());
+        for path in log_files(&config) {
+            let metadata = std::fs::metadata(path).unwrap();
+            assert!(metadata.len() <= 400);
+            #[cfg(unix)]
+            assert_eq!(std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o777, 0o600);
+        }
+
+        // Only what the kept files hold is returned, newest first
+        let records = log.query(&DeliveryFilter::default()).unwrap();
+        assert!(records.len() < 20);
+        assert_eq!(records[0].request_id, "req-19");
+    }
+}
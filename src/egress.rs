@@ -0,0 +1,258 @@
+//! Egress policy for outbound requests.
+//!
+//! Every client in the [`ClientPool`](crate::client_pool::ClientPool) resolves
+//! hosts through [`EgressResolver`], so the addresses a request actually
+//! connects to are checked after DNS resolution and a host that re-resolves
+//! to an internal address is still blocked. Literal IP URLs and pinned
+//! addresses bypass DNS and are checked with [`EgressPolicy::check_url`] and
+//! [`EgressPolicy::check_addr`]. Behind a proxy, only the proxy's own host
+//! reaches the resolver, so target hosts are resolved and checked up front
+//! with [`EgressPolicy::check_proxied`].
+
+use crate::config::EgressConfig;
+use crate::sources::{canonical, parse_net};
+use hyper::client::connect::dns::Name;
+use ipnet::IpNet;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::Url;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, OnceLock};
+
+/// Loopback, private, link-local, shared, documentation, multicast and
+/// reserved ranges.
+const NON_PUBLIC_RANGES: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.0.0.0/24",
+    "192.0.2.0/24",
+    "192.168.0.0/16",
+    "198.18.0.0/15",
+    "198.51.100.0/24",
+    "203.0.113.0/24",
+    "224.0.0.0/4",
+    "240.0.0.0/4",
+    "::/128",
+    "::1/128",
+    "2001:db8::/32",
+    "fc00::/7",
+    "fe80::/10",
+    "ff00::/8",
+];
+
+fn non_public_ranges() -> &'static [IpNet] {
+    static RANGES: OnceLock<Vec<IpNet>> = OnceLock::new();
+    RANGES.get_or_init(|| {
+        NON_PUBLIC_RANGES
+            .iter()
+            .map(|range| range.parse().expect("invalid built-in range"))
+            .collect()
+    })
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EgressPolicy {
+    allow_private: bool,
+    allow_hosts: Vec<String>,
+    deny_hosts: Vec<String>,
+    allow_nets: Vec<IpNet>,
+    deny_nets: Vec<IpNet>,
+}
+
+impl EgressPolicy {
+    pub fn compile(config: &EgressConfig) -> Result<Self, String> {
+        let nets = |entries: &[String], field: &str| {
+            entries
+                .iter()
+                .map(|entry| parse_net(entry))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("settings.egress.{}: {}", field, e))
+        };
+        let hosts = |entries: &[String]| entries.iter().map(|h| normalize_host(h)).collect();
+
+        Ok(Self {
+            allow_private: config.allow_private,
+            allow_hosts: hosts(&config.allow_hosts),
+            deny_hosts: hosts(&config.deny_hosts),
+            allow_nets: nets(&config.allow_cidrs, "allow_cidrs")?,
+            deny_nets: nets(&config.deny_cidrs, "deny_cidrs")?,
+        })
+    }
+
+    /// Check a host name against `deny_hosts`.
+    pub fn check_host(&self, host: &str) -> Result<(), String> {
+        let host = normalize_host(host);
+        if self.deny_hosts.iter().any(|pattern| host_matches(pattern, &host)) {
+            return Err(format!("Egress policy blocks host {}", host));
+        }
+        Ok(())
+    }
+
+    /// Check an address `host` resolved to.
+    pub fn check_addr(&self, host: &str, ip: IpAddr) -> Result<(), String> {
+        let ip = canonical(ip);
+        if self.deny_nets.iter().any(|net| net.contains(&ip)) {
+            return Err(format!("Egress policy blocks {} ({}): denied range", host, ip));
+        }
+        if self.allow_private || self.allow_nets.iter().any(|net| net.contains(&ip)) {
+            return Ok(());
+        }
+        let host = normalize_host(host);
+        if self.allow_hosts.iter().any(|pattern| host_matches(pattern, &host)) {
+            return Ok(());
+        }
+        if non_public_ranges().iter().any(|net| net.contains(&ip)) {
+            return Err(format!(
+                "Egress policy blocks {} ({}): non-public address, allow it with settings.egress",
+                host, ip
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check a URL's host before sending, including literal IP hosts that
+    /// are never passed to the resolver.
+    pub fn check_url(&self, url: &Url) -> Result<(), String> {
+        let host = url.host_str().unwrap_or_default();
+        self.check_host(host)?;
+        match normalize_host(host).parse::<IpAddr>() {
+            Ok(ip) => self.check_addr(host, ip),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Check the target of a request sent through a proxy, which resolves
+    /// and connects to it in our place. Every address the host resolves to
+    /// must be allowed, as the proxy may use any of them; hosts in
+    /// `allow_hosts` are not resolved, so list hosts only the proxy can
+    /// resolve there.
+    pub async fn check_proxied(&self, url: &Url) -> Result<(), String> {
+        let host = normalize_host(url.host_str().unwrap_or_default());
+        // Literal addresses were checked with the URL
+        if host.parse::<IpAddr>().is_ok()
+            || (self.allow_private && self.deny_nets.is_empty())
+            || self.allow_hosts.iter().any(|pattern| host_matches(pattern, &host))
+        {
+            return Ok(());
+        }
+        let port = url.port_or_known_default().unwrap_or(443);
+        let resolved = tokio::net::lookup_host((host.as_str(), port))
+            .await
+            .map_err(|e| format!("Failed to resolve {}: {}", host, e))?;
+        for addr in resolved {
+            self.check_addr(&host, addr.ip())?;
+        }
+        Ok(())
+    }
+
+    /// Resolve `host` and keep the addresses the policy allows, failing when
+    /// none are left.
+    pub async fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, String> {
+        self.check_host(host)?;
+        let resolved: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+            .await
+            .map_err(|e| format!("Failed to resolve {}: {}", host, e))?
+            .collect();
+
+        let mut allowed = Vec::new();
+        let mut last_error = None;
+        for addr in resolved {
+            match self.check_addr(host, addr.ip()) {
+                Ok(()) => allowed.push(addr),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match (allowed.is_empty(), last_error) {
+            (true, Some(e)) => Err(e),
+            (true, None) => Err(format!("Failed to resolve {}: no addresses", host)),
+            (false, _) => Ok(allowed),
+        }
+    }
+}
+
+fn normalize_host(host: &str) -> String {
+    host.trim()
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .trim_end_matches('.')
+        .to_ascii_lowercase()
+}
+
+fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.len() > 1 && prefix.ends_with('.')),
+        None => pattern == host,
+    }
+}
+
+/// DNS resolver for reqwest that applies an [`EgressPolicy`] to every
+/// resolved address.
+#[derive(Debug, Clone)]
+pub struct EgressResolver(pub Arc<EgressPolicy>);
+
+impl Resolve for EgressResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let policy = self.0.clone();
+        Box::pin(async move {
+            let addrs = policy.resolve(name.as_str(), 0).await?;
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(config: serde_json::Value) -> EgressPolicy {
+        EgressPolicy::compile(&serde_json::from_value(config).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_non_public_addresses_blocked_by_default() {
+        let policy = policy(serde_json::json!({}));
+        for url in [
+            "http://169.254.169.254/latest/meta-data/",
+            "http://127.0.0.1:8080/admin",
+            "http://[::1]/",
+            "http://[::ffff:10.0.0.1]/",
+            "http://192.168.1.10/",
+        ] {
+            assert!(policy.check_url(&Url::parse(url).unwrap()).is_err(), "{}", url);
+        }
+        assert!(policy.check_url(&Url::parse("https://93.184.216.34/hook").unwrap()).is_ok());
+        assert!(policy.check_url(&Url::parse("https://hooks.slack.com/services/x").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_allow_and_deny_lists() {
+        let policy = policy(serde_json::json!({
+            "allow_hosts": ["*.svc.cluster.local"],
+            "allow_cidrs": ["10.42.0.0/16"],
+            "deny_hosts": ["metadata.google.internal"],
+            "deny_cidrs": ["10.42.99.0/24"],
+        }));
+
+        assert!(policy.check_addr("alertmanager.monitoring.svc.cluster.local", "10.96.0.10".parse().unwrap()).is_ok());
+        assert!(policy.check_addr("svc.cluster.local", "10.96.0.10".parse().unwrap()).is_err());
+        assert!(policy.check_addr("receiver", "10.42.1.1".parse().unwrap()).is_ok());
+        // Denied ranges win over every allow rule
+        assert!(policy.check_addr("a.svc.cluster.local", "10.42.99.1".parse().unwrap()).is_err());
+        assert!(policy.check_host("Metadata.Google.Internal.").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_checks_resolved_addresses() {
+        let blocked = policy(serde_json::json!({}));
+        let err = blocked.resolve("localhost", 80).await.unwrap_err();
+        assert!(err.contains("non-public"), "{}", err);
+
+        let allowed = policy(serde_json::json!({ "allow_hosts": ["localhost"] }));
+        assert!(!allowed.resolve("localhost", 80).await.unwrap().is_empty());
+    }
+}
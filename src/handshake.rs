@@ -0,0 +1,92 @@
+use crate::config::Handshake;
+use axum::http::{header, Method};
+use axum::response::{IntoResponse, Json, Response};
+use serde_json::Value;
+
+impl Handshake {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Handshake::Slack => "slack",
+            Handshake::Graph => "graph",
+            Handshake::Websub => "websub",
+        }
+    }
+}
+
+/// The response to a verification request, or `None` when the request is a
+/// regular event that should be forwarded.
+///
+/// Runs before the body is decoded, as Graph and WebSub verification
+/// requests may have no body at all.
+pub fn answer(handshake: Handshake, method: &Method, query: Option<&str>, body: &[u8]) -> Option<Response> {
+    match handshake {
+        Handshake::Slack => {
+            let payload: Value = serde_json::from_slice(body).ok()?;
+            if payload.get("type").and_then(Value::as_str) != Some("url_verification") {
+                return None;
+            }
+            let challenge = payload.get("challenge").and_then(Value::as_str)?;
+            Some(Json(serde_json::json!({ "challenge": challenge })).into_response())
+        }
+        Handshake::Graph => {
+            let token = query_param(query, "validationToken")?;
+            Some(text(token))
+        }
+        Handshake::Websub => {
+            if method != Method::GET {
+                return None;
+            }
+            let mode = query_param(query, "hub.mode")?;
+            if mode != "subscribe" && mode != "unsubscribe" {
+                return None;
+            }
+            let challenge = query_param(query, "hub.challenge")?;
+            Some(text(challenge))
+        }
+    }
+}
+
+fn query_param(query: Option<&str>, name: &str) -> Option<String> {
+    serde_urlencoded::from_str::<Vec<(String, String)>>(query?)
+        .ok()?
+        .into_iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value)
+}
+
+fn text(body: String) -> Response {
+    ([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn body(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_slack_url_verification() {
+        let payload = br#"{"type":"url_verification","token":"t","challenge":"3eZbrw1aBm2rZgRNFdxV2595E9CY3gmdALWMmHkvFXO7tYXAYM8P"}"#;
+        let response = answer(Handshake::Slack, &Method::POST, None, payload).unwrap();
+        assert_eq!(body(response).await, r#"{"challenge":"3eZbrw1aBm2rZgRNFdxV2595E9CY3gmdALWMmHkvFXO7tYXAYM8P"}"#);
+
+        let event = br#"{"type":"event_callback","event":{}}"#;
+        assert!(answer(Handshake::Slack, &Method::POST, None, event).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_graph_and_websub_challenges() {
+        let response = answer(Handshake::Graph, &Method::POST, Some("validationToken=Validation%3A%20Testing"), b"").unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain; charset=utf-8");
+        assert_eq!(body(response).await, "Validation: Testing");
+        assert!(answer(Handshake::Graph, &Method::POST, None, b"").is_none());
+
+        let query = Some("hub.mode=subscribe&hub.topic=https%3A%2F%2Fexample.com%2Ffeed&hub.challenge=abc123");
+        let response = answer(Handshake::Websub, &Method::GET, query, b"").unwrap();
+        assert_eq!(body(response).await, "abc123");
+        assert!(answer(Handshake::Websub, &Method::POST, query, b"").is_none());
+    }
+}
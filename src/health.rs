@@ -1,6 +1,16 @@
-use axum::{http::StatusCode, response::Json};
-use serde_json::{json, Value};
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::client_pool::ClientPool;
+use crate::config::{ReadinessConfig, TargetProbe, WebhookRegister};
+use crate::delivery_log::{DeliveryLog, QUEUE_CAPACITY};
+use crate::routes::{Route, RouteTable};
+use axum::{extract::State, http::StatusCode, response::Json};
+use chrono::{DateTime, Utc};
+use reqwest::{Method, Url};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
 
 pub async fn health_check() -> Result<Json<Value>, StatusCode> {
     let timestamp = SystemTime::now()
@@ -16,12 +26,329 @@ pub async fn health_check() -> Result<Json<Value>, StatusCode> {
     })))
 }
 
-pub async fn readiness_check() -> Result<Json<Value>, StatusCode> {
-    // Add any readiness checks here (database connections, etc.)
-    Ok(Json(json!({
-        "status": "ready",
-        "checks": {
-            "config": "ok"
+/// The URL and method a register's probe checks.
+pub fn probe_request(register: &WebhookRegister, probe: &TargetProbe) -> Result<(Url, Method), String> {
+    let url = match &probe.url {
+        Some(url) => url,
+        None if register.target.url.contains("{{") => {
+            return Err("probe.url is required when the target URL is a template".to_string())
         }
-    })))
+        None => &register.target.url,
+    };
+    let method = Method::from_bytes(probe.method.to_uppercase().as_bytes())
+        .map_err(|_| format!("invalid probe method {}", probe.method))?;
+    Ok((crate::target::validate_url(url)?, method))
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ProbeResult {
+    reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    http_status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    checked_at: DateTime<Utc>,
+}
+
+/// A target reachability probe and its latest result.
+struct Probe {
+    endpoint: String,
+    /// The route probed, replaced along with the probe when it changes
+    route: Arc<Route>,
+    url: Url,
+    method: Method,
+    interval: Duration,
+    timeout: Duration,
+    required: bool,
+    last: RwLock<Option<ProbeResult>>,
+}
+
+impl Probe {
+    async fn check(&self, clients: &ClientPool) {
+        let response = async {
+            let prepared = clients.prepare(&self.route.register.target, self.url.clone()).await?;
+            let mut request = prepared.client.request(self.method.clone(), prepared.url).timeout(self.timeout);
+            if let Some(host) = prepared.host_header {
+                request = request.header(reqwest::header::HOST, host);
+            }
+            request.send().await.map_err(|e| e.to_string())
+        }
+        .await;
+
+        let result = match response {
+            Ok(response) => ProbeResult {
+                reachable: response.status().as_u16() < 500,
+                http_status: Some(response.status().as_u16()),
+                error: None,
+                checked_at: Utc::now(),
+            },
+            Err(e) => ProbeResult {
+                reachable: false,
+                http_status: None,
+                error: Some(crate::secrets::redact(&e)),
+                checked_at: Utc::now(),
+            },
+        };
+        let previous = self.last.write().unwrap_or_else(|e| e.into_inner()).replace(result.clone());
+        if previous.is_none_or(|p| p.reachable != result.reachable) {
+            if result.reachable {
+                info!(endpoint = %self.endpoint, status = ?result.http_status, "Target reachable");
+            } else {
+                warn!(endpoint = %self.endpoint, status = ?result.http_status, error = ?result.error, "Target unreachable");
+            }
+        }
+    }
+
+    /// The result as reported, and whether it fails readiness.
+    fn report(&self) -> (Value, bool) {
+        let mut url = self.url.clone();
+        let _ = url.set_username("");
+        let _ = url.set_password(None);
+        url.set_query(None);
+
+        let last = self.last.read().unwrap_or_else(|e| e.into_inner()).clone();
+        let status = match &last {
+            None => "pending",
+            Some(result) if result.reachable => "ok",
+            Some(_) => "failing",
+        };
+        let mut report = json!({
+            "status": status,
+            "required": self.required,
+            "url": url.to_string(),
+        });
+        if let (Some(result), Value::Object(map)) = (&last, &mut report) {
+            if let Ok(Value::Object(fields)) = serde_json::to_value(result) {
+                map.extend(fields.into_iter().filter(|(k, _)| k != "reachable"));
+            }
+        }
+        (report, self.required && status != "ok")
+    }
+}
+
+/// What `/ready` checks: the loaded configuration, the delivery log queue,
+/// target probes and whether the server is shutting down. The registers are
+/// read from the route table, so probes follow admin API changes.
+pub struct Readiness {
+    routes: Arc<RouteTable>,
+    queue_high_water: usize,
+    delivery_log: Option<Arc<DeliveryLog>>,
+    probes: RwLock<Vec<Arc<Probe>>>,
+    draining: AtomicBool,
+}
+
+impl Readiness {
+    pub fn new(routes: Arc<RouteTable>, config: &ReadinessConfig, delivery_log: Option<Arc<DeliveryLog>>) -> Self {
+        let readiness = Self {
+            routes,
+            queue_high_water: config.queue_high_water,
+            delivery_log,
+            probes: RwLock::new(Vec::new()),
+            draining: AtomicBool::new(false),
+        };
+        readiness.sync_probes();
+        readiness
+    }
+
+    /// Match the probes to the current routes, keeping those of unchanged
+    /// routes, and return the probes created.
+    fn sync_probes(&self) -> Vec<Arc<Probe>> {
+        let mut probes = self.probes.write().unwrap_or_else(|e| e.into_inner());
+        let mut created = Vec::new();
+        let mut synced = Vec::new();
+        for route in self.routes.current().iter() {
+            let register = &route.register;
+            let Some(probe) = &register.probe else { continue };
+            if let Some(kept) = probes.iter().find(|p| Arc::ptr_eq(&p.route, route)) {
+                synced.push(kept.clone());
+                continue;
+            }
+            // Checked when the route compiled
+            let Ok((url, method)) = probe_request(register, probe) else { continue };
+            let probe = Arc::new(Probe {
+                endpoint: register.endpoint.clone(),
+                route: route.clone(),
+                url,
+                method,
+                interval: Duration::from_secs(probe.interval_seconds.max(1)),
+                timeout: Duration::from_secs(probe.timeout_seconds.max(1)),
+                required: probe.required,
+                last: RwLock::new(None),
+            });
+            created.push(probe.clone());
+            synced.push(probe);
+        }
+        synced.sort_by(|a, b| a.endpoint.cmp(&b.endpoint));
+        *probes = synced;
+        created
+    }
+
+    /// Run every probe now and then at its interval, starting and stopping
+    /// probes as registers change.
+    pub fn spawn_probes(self: Arc<Self>, clients: Arc<ClientPool>) {
+        let mut changes = self.routes.changes();
+        tokio::spawn(async move {
+            let mut created = self.probes.read().unwrap_or_else(|e| e.into_inner()).clone();
+            loop {
+                for probe in created {
+                    tokio::spawn(run_probe(Arc::downgrade(&probe), clients.clone()));
+                }
+                if changes.changed().await.is_err() {
+                    return;
+                }
+                created = self.sync_probes();
+            }
+        });
+    }
+
+    /// Report not ready from now on.
+    pub fn start_draining(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+
+    fn report(&self) -> (bool, Value) {
+        let draining = self.draining.load(Ordering::Relaxed);
+        let mut ready = !draining;
+
+        let queue = match &self.delivery_log {
+            None => json!({ "status": "disabled" }),
+            Some(log) => {
+                let state = log.queue_state();
+                let ok = state.error.is_none() && state.pending < self.queue_high_water;
+                ready &= ok;
+                json!({
+                    "status": if ok { "ok" } else { "failing" },
+                    "pending": state.pending,
+                    "high_water": self.queue_high_water,
+                    "capacity": QUEUE_CAPACITY,
+                    "error": state.error,
+                })
+            }
+        };
+
+        let mut targets = Map::new();
+        for probe in self.probes.read().unwrap_or_else(|e| e.into_inner()).iter() {
+            let (report, failing) = probe.report();
+            ready &= !failing;
+            targets.insert(probe.endpoint.clone(), report);
+        }
+
+        let report = json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "draining": draining,
+            "checks": {
+                "config": { "status": "ok", "registers": self.routes.current().iter().count() },
+                "delivery_queue": queue,
+                "targets": targets,
+            }
+        });
+        (ready, report)
+    }
+}
+
+/// Check a probe at its interval until it is replaced.
+async fn run_probe(probe: Weak<Probe>, clients: Arc<ClientPool>) {
+    let Some(interval) = probe.upgrade().map(|p| p.interval) else { return };
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let Some(probe) = probe.upgrade() else { return };
+        probe.check(&clients).await;
+    }
+}
+
+pub async fn readiness_check(State(readiness): State<Arc<Readiness>>) -> (StatusCode, Json<Value>) {
+    let (ready, report) = readiness.report();
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EgressConfig;
+    use crate::egress::EgressPolicy;
+    use crate::routes::Change;
+
+    fn register(endpoint: &str, url: &str, probe: &str) -> serde_yaml::Value {
+        serde_yaml::from_str(&format!(
+            "endpoint: {}\nmethod: POST\ntarget: {{ url: '{}', method: POST }}\ntemplate: '{{}}'\nprobe: {}",
+            endpoint, url, probe
+        ))
+        .unwrap()
+    }
+
+    fn compile(registers: Vec<serde_yaml::Value>) -> (Readiness, Arc<ClientPool>) {
+        let egress = EgressPolicy::compile(&EgressConfig {
+            allow_private: true,
+            ..Default::default()
+        })
+        .unwrap();
+        let clients = Arc::new(ClientPool::new(Duration::from_secs(5), egress).unwrap());
+        let routes = RouteTable::new(registers, &Default::default(), clients.clone(), None).unwrap();
+        (Readiness::new(Arc::new(routes), &ReadinessConfig::default(), None), clients)
+    }
+
+    fn probe(readiness: &Readiness, endpoint: &str) -> Arc<Probe> {
+        let probes = readiness.probes.read().unwrap();
+        probes.iter().find(|p| p.endpoint == endpoint).unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn test_required_probe_and_draining() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let target = axum::Router::new().route("/down", axum::routing::any(|| async { StatusCode::BAD_GATEWAY }));
+        tokio::spawn(async move { axum::serve(listener, target).await.unwrap() });
+        let down = format!("http://{}/down", addr);
+
+        let (readiness, clients) = compile(vec![register("/alerts", &down, "{ required: true }")]);
+        let (ready, report) = readiness.report();
+        assert!(!ready);
+        assert_eq!(report["checks"]["targets"]["/alerts"]["status"], "pending");
+
+        probe(&readiness, "/alerts").check(&clients).await;
+        let (ready, report) = readiness.report();
+        assert!(!ready);
+        assert_eq!(report["checks"]["targets"]["/alerts"]["http_status"], 502);
+
+        // Optional probes are reported but never fail readiness
+        let (readiness, clients) = compile(vec![register("/alerts", &down, "{}")]);
+        probe(&readiness, "/alerts").check(&clients).await;
+        assert!(readiness.report().0);
+        readiness.start_draining();
+        let (ready, report) = readiness.report();
+        assert!(!ready);
+        assert_eq!(report["draining"], true);
+    }
+
+    #[test]
+    fn test_probes_follow_register_changes() {
+        let (readiness, _) = compile(vec![register("/alerts", "https://chat.example.com/hooks", "{}")]);
+        let routes = readiness.routes.clone();
+        let alerts = probe(&readiness, "/alerts");
+
+        routes
+            .apply(Change::Create(register("/deploys", "https://ci.example.com/hooks", "{ required: true }")), false)
+            .unwrap();
+        let created = readiness.sync_probes();
+        assert_eq!(created.len(), 1);
+        assert!(Arc::ptr_eq(&probe(&readiness, "/alerts"), &alerts));
+        let (ready, report) = readiness.report();
+        assert!(!ready);
+        assert_eq!(report["checks"]["config"]["registers"], 2);
+        assert_eq!(report["checks"]["targets"]["/deploys"]["status"], "pending");
+
+        routes.apply(Change::Delete("/deploys".to_string()), false).unwrap();
+        assert!(readiness.sync_probes().is_empty());
+        let (ready, report) = readiness.report();
+        assert!(ready);
+        assert_eq!(report["checks"]["config"]["registers"], 1);
+        assert!(report["checks"]["targets"].get("/deploys").is_none());
+
+        // Probes are checked as the register compiles
+        let templated = register("/templated", "https://example.com/{{id}}", "{}");
+        assert!(routes.apply(Change::Create(templated), false).is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,250 @@
+use axum::body::Bytes;
+use quick_xml::events::{BytesStart, Event};
+use serde_json::{Map, Value};
+
+/// Decode an inbound request body into a JSON value for the template context,
+/// based on its Content-Type.
+///
+/// - JSON (or no Content-Type): parsed as-is
+/// - `application/x-www-form-urlencoded`: an object of fields, repeated fields
+///   become arrays; a lone `payload` field holding JSON is unwrapped
+/// - XML: the root element as an object, attributes as `@name`, text content
+///   as `#text` when the element also has attributes or children
+/// - `text/*`: `{"text": body}`
+/// - `multipart/form-data`: an object of fields, files as objects with
+///   `filename`, `content_type`, `size` and, when UTF-8, `content`
+pub async fn decode_payload(content_type: Option<&str>, body: &Bytes) -> Result<Value, String> {
+    let mime = content_type
+        .and_then(|ct| ct.split(';').next())
+        .map(|ct| ct.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match mime.as_str() {
+        "application/x-www-form-urlencoded" => decode_form(body),
+        "multipart/form-data" => decode_multipart(content_type.unwrap_or_default(), body.clone()).await,
+        "application/xml" | "text/xml" => decode_xml(&utf8(body)?),
+        m if m.ends_with("+xml") => decode_xml(&utf8(body)?),
+        m if m.starts_with("text/") => Ok(serde_json::json!({ "text": utf8(body)? })),
+        _ => serde_json::from_slice(body).map_err(|e| format!("Invalid JSON: {}", e)),
+    }
+}
+
+fn utf8(body: &[u8]) -> Result<String, String> {
+    String::from_utf8(body.to_vec()).map_err(|e| format!("Body is not valid UTF-8: {}", e))
+}
+
+fn decode_form(body: &[u8]) -> Result<Value, String> {
+    let pairs: Vec<(String, String)> =
+        serde_urlencoded::from_bytes(body).map_err(|e| format!("Invalid form data: {}", e))?;
+
+    // Old GitHub-style `payload=<json>` bodies carry the real payload in one field
+    if let [(key, value)] = pairs.as_slice() {
+        if key == "payload" {
+            if let Ok(json) = serde_json::from_str::<Value>(value) {
+                return Ok(json);
+            }
+        }
+    }
+
+    let mut map = Map::new();
+    for (key, value) in pairs {
+        insert_field(&mut map, key, Value::String(value));
+    }
+    Ok(Value::Object(map))
+}
+
+async fn decode_multipart(content_type: &str, body: Bytes) -> Result<Value, String> {
+    let boundary =
+        multer::parse_boundary(content_type).map_err(|e| format!("Invalid multipart body: {}", e))?;
+    let stream = futures_util::stream::once(async move { Ok::<_, std::io::Error>(body) });
+    let mut multipart = multer::Multipart::new(stream, boundary);
+
+    let mut map = Map::new();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| format!("Invalid multipart body: {}", e))?
+    {
+        let name = field.name().unwrap_or_default().to_string();
+        let file_name = field.file_name().map(str::to_string);
+        let field_type = field.content_type().map(|m| m.to_string());
+        let data = field
+            .bytes()
+            .await
+            .map_err(|e| format!("Invalid multipart body: {}", e))?;
+
+        let value = match file_name {
+            Some(file_name) => {
+                let mut file = Map::new();
+                file.insert("filename".to_string(), Value::String(file_name));
+                file.insert(
+                    "content_type".to_string(),
+                    field_type.map(Value::String).unwrap_or(Value::Null),
+                );
+                file.insert("size".to_string(), Value::from(data.len()));
+                if let Ok(text) = std::str::from_utf8(&data) {
+                    file.insert("content".to_string(), Value::String(text.to_string()));
+                }
+                Value::Object(file)
+            }
+            None => Value::String(String::from_utf8_lossy(&data).into_owned()),
+        };
+        insert_field(&mut map, name, value);
+    }
+    Ok(Value::Object(map))
+}
+
+fn decode_xml(body: &str) -> Result<Value, String> {
+    let mut reader = quick_xml::Reader::from_str(body);
+    reader.config_mut().trim_text(true);
+
+    // Each open element keeps its name, attributes/children and text content
+    let mut stack: Vec<(String, Map<String, Value>, String)> = vec![(String::new(), Map::new(), String::new())];
+    let xml_error = |e: quick_xml::Error| format!("Invalid XML: {}", e);
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(start) => {
+                let (name, attributes) = xml_element(&start)?;
+                stack.push((name, attributes, String::new()));
+            }
+            Event::Empty(start) => {
+                let (name, attributes) = xml_element(&start)?;
+                let parent = &mut stack.last_mut().expect("xml stack is never empty").1;
+                insert_field(parent, name, xml_value(attributes, String::new()));
+            }
+            Event::Text(text) => {
+                let text = text.unescape().map_err(xml_error)?;
+                stack.last_mut().expect("xml stack is never empty").2.push_str(&text);
+            }
+            Event::CData(data) => {
+                let text = data.decode().map_err(|e| xml_error(e.into()))?;
+                stack.last_mut().expect("xml stack is never empty").2.push_str(&text);
+            }
+            Event::End(_) => {
+                if stack.len() < 2 {
+                    return Err("Invalid XML: unexpected closing tag".to_string());
+                }
+                let (name, attributes, text) = stack.pop().expect("checked above");
+                let parent = &mut stack.last_mut().expect("xml stack is never empty").1;
+                insert_field(parent, name, xml_value(attributes, text));
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if stack.len() != 1 {
+        return Err("Invalid XML: unclosed element".to_string());
+    }
+    let (_, root, _) = stack.pop().expect("checked above");
+    if root.is_empty() {
+        return Err("Invalid XML: no root element".to_string());
+    }
+    Ok(Value::Object(root))
+}
+
+fn xml_element(start: &BytesStart) -> Result<(String, Map<String, Value>), String> {
+    let name = String::from_utf8_lossy(start.local_name().as_ref()).into_owned();
+    let mut attributes = Map::new();
+    for attribute in start.attributes() {
+        let attribute = attribute.map_err(|e| format!("Invalid XML attribute: {}", e))?;
+        let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned();
+        let value = attribute
+            .unescape_value()
+            .map_err(|e| format!("Invalid XML attribute: {}", e))?;
+        attributes.insert(format!("@{}", key), Value::String(value.into_owned()));
+    }
+    Ok((name, attributes))
+}
+
+fn xml_value(mut fields: Map<String, Value>, text: String) -> Value {
+    if fields.is_empty() {
+        return Value::String(text);
+    }
+    if !text.is_empty() {
+        fields.insert("#text".to_string(), Value::String(text));
+    }
+    Value::Object(fields)
+}
+
+/// Insert a field, turning repeated keys into an array of values.
+fn insert_field(map: &mut Map<String, Value>, key: String, value: Value) {
+    match map.get_mut(&key) {
+        Some(Value::Array(values)) => values.push(value),
+        Some(existing) => {
+            let first = existing.take();
+            *existing = Value::Array(vec![first, value]);
+        }
+        None => {
+            map.insert(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    async fn decode(content_type: &str, body: &str) -> Value {
+        decode_payload(Some(content_type), &Bytes::from(body.to_string()))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_decode_form() {
+        let value = decode(
+            "application/x-www-form-urlencoded",
+            "command=%2Fdeploy&text=api+prod&channel=ops&channel=dev",
+        )
+        .await;
+        assert_eq!(
+            value,
+            json!({"command": "/deploy", "text": "api prod", "channel": ["ops", "dev"]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decode_form_wrapped_json() {
+        let value = decode(
+            "application/x-www-form-urlencoded",
+            "payload=%7B%22action%22%3A%22opened%22%7D",
+        )
+        .await;
+        assert_eq!(value, json!({"action": "opened"}));
+    }
+
+    #[tokio::test]
+    async fn test_decode_xml() {
+        let value = decode(
+            "application/xml; charset=utf-8",
+            r#"<alert id="7"><severity>high</severity><host>a</host><host>b</host><note><![CDATA[x < y]]></note></alert>"#,
+        )
+        .await;
+        assert_eq!(
+            value,
+            json!({"alert": {"@id": "7", "severity": "high", "host": ["a", "b"], "note": "x < y"}})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decode_text_and_json() {
+        assert_eq!(decode("text/plain", "hello").await, json!({"text": "hello"}));
+        assert_eq!(decode("application/json", r#"{"a":1}"#).await, json!({"a": 1}));
+        assert!(decode_payload(None, &Bytes::from("nope")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_decode_multipart() {
+        let body = "--XYZ\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nbuild failed\r\n\
+                    --XYZ\r\nContent-Disposition: form-data; name=\"log\"; filename=\"build.log\"\r\n\
+                    Content-Type: text/plain\r\n\r\nerror: boom\r\n--XYZ--\r\n";
+        let value = decode("multipart/form-data; boundary=XYZ", body).await;
+        assert_eq!(value["title"], "build failed");
+        assert_eq!(value["log"]["filename"], "build.log");
+        assert_eq!(value["log"]["content"], "error: boom");
+        assert_eq!(value["log"]["size"], 11);
+    }
+}
@@ -0,0 +1,158 @@
+use axum::body::{Body, Bytes};
+use axum::http::StatusCode;
+use futures_util::StreamExt;
+use std::fmt;
+use std::io::Read;
+use std::time::Duration;
+
+/// Why an inbound body could not be read.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BodyError {
+    /// The body, compressed or decompressed, exceeds the limit in bytes
+    TooLarge(usize),
+    /// The client did not send the whole body in time
+    Timeout,
+    UnsupportedEncoding(String),
+    Invalid(String),
+}
+
+impl BodyError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            BodyError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            BodyError::Timeout => StatusCode::REQUEST_TIMEOUT,
+            BodyError::UnsupportedEncoding(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            BodyError::Invalid(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BodyError::TooLarge(limit) => write!(f, "Request body exceeds {} bytes", limit),
+            BodyError::Timeout => write!(f, "Timed out reading request body"),
+            BodyError::UnsupportedEncoding(encoding) => write!(f, "Unsupported Content-Encoding: {}", encoding),
+            BodyError::Invalid(e) => write!(f, "Invalid request body: {}", e),
+        }
+    }
+}
+
+/// Read a request body of at most `limit` bytes within `timeout`.
+///
+/// A `Content-Length` over the limit is rejected before anything is read.
+pub async fn read_body(
+    body: Body,
+    content_length: Option<u64>,
+    limit: usize,
+    timeout: Duration,
+) -> Result<Bytes, BodyError> {
+    if content_length.is_some_and(|len| len > limit as u64) {
+        return Err(BodyError::TooLarge(limit));
+    }
+
+    let read = async {
+        let mut stream = body.into_data_stream();
+        let mut buffer = Vec::new();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| BodyError::Invalid(e.to_string()))?;
+            if buffer.len() + chunk.len() > limit {
+                return Err(BodyError::TooLarge(limit));
+            }
+            buffer.extend_from_slice(&chunk);
+        }
+        Ok(Bytes::from(buffer))
+    };
+
+    tokio::time::timeout(timeout, read)
+        .await
+        .map_err(|_| BodyError::Timeout)?
+}
+
+/// Undo the `Content-Encoding` of a body (gzip, deflate and br, possibly
+/// several), stopping as soon as the output grows past `limit` bytes so
+/// small compressed bombs cannot exhaust memory.
+pub fn decompress(content_encoding: Option<&str>, body: Bytes, limit: usize) -> Result<Bytes, BodyError> {
+    let encodings: Vec<String> = content_encoding
+        .unwrap_or_default()
+        .split(',')
+        .map(|e| e.trim().to_ascii_lowercase())
+        .filter(|e| !e.is_empty() && e != "identity")
+        .collect();
+
+    // Encodings are listed in the order they were applied
+    let mut body = body;
+    for encoding in encodings.iter().rev() {
+        body = match encoding.as_str() {
+            "gzip" | "x-gzip" => read_limited(flate2::read::MultiGzDecoder::new(&body[..]), limit)?,
+            // "deflate" is zlib-wrapped, but some senders use raw deflate
+            "deflate" => read_limited(flate2::read::ZlibDecoder::new(&body[..]), limit).or_else(|e| match e {
+                BodyError::Invalid(_) => read_limited(flate2::read::DeflateDecoder::new(&body[..]), limit),
+                e => Err(e),
+            })?,
+            "br" => read_limited(brotli::Decompressor::new(&body[..], 4096), limit)?,
+            other => return Err(BodyError::UnsupportedEncoding(other.to_string())),
+        };
+    }
+    Ok(body)
+}
+
+fn read_limited(reader: impl Read, limit: usize) -> Result<Bytes, BodyError> {
+    let mut output = Vec::new();
+    reader
+        .take(limit as u64 + 1)
+        .read_to_end(&mut output)
+        .map_err(|e| BodyError::Invalid(e.to_string()))?;
+    if output.len() > limit {
+        return Err(BodyError::TooLarge(limit));
+    }
+    Ok(Bytes::from(output))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Bytes {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        Bytes::from(encoder.finish().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_read_body_limits() {
+        let body = read_body(Body::from("hello"), Some(5), 5, Duration::from_secs(1)).await.unwrap();
+        assert_eq!(&body[..], b"hello");
+
+        let err = read_body(Body::from("hello"), Some(5), 4, Duration::from_secs(1)).await.unwrap_err();
+        assert_eq!(err, BodyError::TooLarge(4));
+        // Bodies without a Content-Length are cut off while streaming
+        let err = read_body(Body::from("hello"), None, 4, Duration::from_secs(1)).await.unwrap_err();
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[test]
+    fn test_decompress_encodings() {
+        let json = br#"{"alert":"firing"}"#;
+        assert_eq!(&decompress(Some("gzip"), gzip(json), 1024).unwrap()[..], json);
+
+        let mut br = Vec::new();
+        brotli::CompressorWriter::new(&mut br, 4096, 5, 22).write_all(json).unwrap();
+        let mut deflate = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        deflate.write_all(&br).unwrap();
+        let layered = Bytes::from(deflate.finish().unwrap());
+        assert_eq!(&decompress(Some("br, deflate"), layered, 1024).unwrap()[..], json);
+
+        let err = decompress(Some("zstd"), Bytes::from_static(json), 1024).unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[test]
+    fn test_decompress_stops_at_limit() {
+        // 10 MiB of zeros compresses to about 10 KiB
+        let bomb = gzip(&vec![0u8; 10 * 1024 * 1024]);
+        assert!(bomb.len() < 64 * 1024);
+        assert_eq!(decompress(Some("gzip"), bomb, 1024 * 1024).unwrap_err(), BodyError::TooLarge(1024 * 1024));
+    }
+}
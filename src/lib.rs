@@ -1,6 +1,32 @@
 pub mod config;
 pub mod health;
 pub mod admin;
+pub mod admin_api;
+pub mod body;
+pub mod client_pool;
+pub mod debug_bin;
+pub mod delivery;
+pub mod delivery_log;
+pub mod egress;
+pub mod handshake;
+pub mod inbound;
+pub mod inbound_auth;
+pub mod inbound_body;
+pub mod pause;
+pub mod redaction;
+pub mod replay;
+pub mod request_id;
+pub mod response_policy;
+pub mod routes;
+pub mod script;
+pub mod secrets;
+pub mod sources;
+pub mod tail;
+pub mod target;
+pub mod target_auth;
+pub mod telemetry;
+pub mod tls;
+pub mod wasm;
 
 pub use config::*;
 pub use health::*;
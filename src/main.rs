@@ -1,72 +1,159 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
+    body::Body,
+    extract::{ConnectInfo, Path, RawQuery, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Json, Response},
     routing::{any, get},
     Router,
 };
 use clap::Parser;
-use handlebars::{Helper, Handlebars, Context, RenderContext, Output, HelperResult};
 
-use reqwest::Client;
 use serde::Serialize;
 use serde_json::{Map, Value};
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use sha2::{Digest, Sha256};
+use std::{collections::{BTreeMap, HashMap}, net::{IpAddr, SocketAddr}, sync::Arc, time::Duration};
+use axum_server::tls_rustls::RustlsConfig;
 use tokio::net::TcpListener;
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
-use tracing::{info, warn};
+use tracing::{info, info_span, warn, Instrument};
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 pub mod config;
 pub mod health;
 pub mod admin;
+pub mod admin_api;
+pub mod body;
+pub mod client_pool;
+pub mod debug_bin;
+pub mod delivery;
+pub mod delivery_log;
+pub mod egress;
+pub mod handshake;
+pub mod inbound;
+pub mod inbound_auth;
+pub mod inbound_body;
+pub mod pause;
+pub mod redaction;
+pub mod replay;
+pub mod request_id;
+pub mod response_policy;
+pub mod routes;
+pub mod script;
+pub mod secrets;
+pub mod sources;
+pub mod tail;
+pub mod target;
+pub mod target_auth;
+pub mod telemetry;
+pub mod tls;
+pub mod wasm;
 
-use config::{Args, Config, WebhookRegister};
+use body::{encode_body, EncodedBody};
+use client_pool::ClientPool;
+use config::{Args, Config, RetryConfig, WebhookRegister};
+use debug_bin::DebugBin;
+use delivery_log::{DeliveryLog, DeliveryRecord, Outcome};
+use egress::EgressPolicy;
+use inbound_auth::InboundAuthenticator;
+use pause::{BufferedRequest, Hold, PauseControl};
+use redaction::Redactor;
+use routes::{Route, RouteTable, Routes};
+use tail::Tail;
+use target::RenderedTarget;
+use target_auth::TokenCache;
 
 
 #[derive(Debug, Serialize)]
 struct ErrorResponse {
+    #[serde(serialize_with = "secrets::serialize_redacted")]
     error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ErrorResponse {
+    fn new(error: impl Into<String>) -> Self {
+        Self {
+            error: error.into(),
+            request_id: None,
+        }
+    }
 }
 
 #[derive(Clone)]
 struct AppState {
-    registers: HashMap<String, WebhookRegister>,
-    handlebars: Arc<Handlebars<'static>>,
-    http_client: Client,
-    config: Config,
+    routes: Arc<RouteTable>,
+    max_body_bytes: usize,
+    body_read_timeout: Duration,
+    clients: Arc<ClientPool>,
+    token_cache: Arc<TokenCache>,
+    request_id_header: Option<reqwest::header::HeaderName>,
+    delivery_log: Option<Arc<DeliveryLog>>,
+    tail: Tail,
+    pause: Arc<PauseControl>,
+    /// Retries for registers without a `retry_config`
+    default_retry: RetryConfig,
 }
 
 impl AppState {
-    fn new(config: Config, args: &Args) -> Self {
-        let mut registers = HashMap::new();
-        let mut handlebars = Handlebars::new();
-        handlebars.register_helper("escapeNewlines", Box::new(escape_newlines_helper));
-
-        // Register templates and build endpoint map
-        for (index, register) in config.registers.iter().enumerate() {
-            let template_name = format!("template_{}", index);
-            handlebars
-                .register_template_string(&template_name, &register.template)
-                .expect("Failed to register template");
-
-            let mut register_with_template = register.clone();
-            register_with_template.template = template_name;
-            registers.insert(register.endpoint.clone(), register_with_template);
-        }
+    /// `source` is the configuration file as written, which the registers
+    /// are compiled from so admin API changes can be persisted unresolved.
+    fn new(config: Config, source: &serde_yaml::Value, args: &Args) -> Self {
+        // Configure HTTP clients with timeout; per-target TLS and proxy
+        // settings are checked as the registers are compiled
+        let egress = EgressPolicy::compile(&config.settings.egress)
+            .unwrap_or_else(|e| panic!("Invalid egress policy: {}", e));
+        let clients = Arc::new(
+            ClientPool::new(Duration::from_secs(args.request_timeout), egress).expect("Failed to create HTTP client"),
+        );
+
+        // Compile every register's templates, target, auth, script and wasm
+        let sources = source["registers"].as_sequence().cloned().unwrap_or_default();
+        let persist_path = config
+            .settings
+            .admin
+            .as_ref()
+            .filter(|admin| admin.persist)
+            .map(|_| args.config.clone());
+        let routes = RouteTable::new(sources, &config.settings, clients.clone(), persist_path)
+            .unwrap_or_else(|e| panic!("Invalid register: {}", e));
+
+        let token_cache = Arc::new(TokenCache::new(clients.default_client().clone()));
+
+        let pause = PauseControl::new(&config.settings.pause)
+            .unwrap_or_else(|e| panic!("Invalid pause settings: {}", e));
+
+        let request_id_header = config
+            .settings
+            .request_id_header
+            .as_deref()
+            .map(|name| request_id::forward_header(name).unwrap_or_else(|e| panic!("{}", e)));
+
+        let redactor = Redactor::compile(&config.settings.redaction)
+            .unwrap_or_else(|e| panic!("Invalid redaction rules: {}", e));
+        redaction::install(redactor);
 
-        // Configure HTTP client with timeout
-        let http_client = Client::builder()
-            .timeout(Duration::from_secs(args.request_timeout))
-            .build()
-            .expect("Failed to create HTTP client");
+        let delivery_log = config
+            .settings
+            .delivery_log
+            .as_ref()
+            .map(|log| Arc::new(DeliveryLog::open(log).unwrap_or_else(|e| panic!("{}", e))));
 
         Self {
-            registers,
-            handlebars: Arc::new(handlebars),
-            http_client,
-            config,
+            routes: Arc::new(routes),
+            max_body_bytes: args.max_body_bytes,
+            body_read_timeout: Duration::from_secs(args.body_read_timeout),
+            clients,
+            token_cache,
+            request_id_header,
+            delivery_log,
+            tail: Tail::default(),
+            pause: Arc::new(pause),
+            default_retry: config.settings.default_retry(),
         }
     }
 }
@@ -74,137 +161,605 @@ impl AppState {
 async fn handle_webhook(
     State(state): State<AppState>,
     Path(path): Path<String>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    method: Method,
+    RawQuery(query): RawQuery,
+    mut headers: HeaderMap,
+    body: Body,
+) -> Response {
+    let request_id = request_id::assign(&mut headers);
+    let routes = state.routes.current();
+    let inbound = Inbound {
+        endpoint: format!("/{}", path),
+        client_ip: routes.sources.client_ip(peer, &headers),
+        received_at: chrono::Utc::now(),
+        request_id,
+        method,
+        query,
+        headers,
+    };
+
+    // Continue the sender's trace when it sent a traceparent; this only
+    // fails when trace export is off, in which case there is nothing to link
+    let span = info_span!(
+        "webhook.receive",
+        otel.kind = "server",
+        request_id = %inbound.request_id,
+        endpoint = %inbound.endpoint,
+        http.method = %inbound.method,
+    );
+    let _ = span.set_parent(telemetry::extract_context(&inbound.headers));
+
+    let mut record = None;
+    let result = process_webhook(&state, &routes, &inbound, body, &mut record)
+        .instrument(span)
+        .await;
+
+    if let Some(mut record) = record {
+        record.finish(result.as_ref().err().map(|(_, Json(e))| e.error.clone()));
+        state.tail.publish(&record);
+        if let Some(log) = &state.delivery_log {
+            log.record(record);
+        }
+    }
+
+    // Return the request ID to the sender, in the error JSON as well
+    let mut response = match result {
+        Ok(response) => response,
+        Err((status, Json(mut error))) => {
+            error.request_id = Some(inbound.request_id.clone());
+            (status, Json(error)).into_response()
+        }
+    };
+    if let Ok(value) = HeaderValue::from_str(&inbound.request_id) {
+        response.headers_mut().insert(request_id::REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// What is known about an inbound request before its body is read.
+struct Inbound {
+    endpoint: String,
+    request_id: String,
+    client_ip: IpAddr,
+    received_at: chrono::DateTime<chrono::Utc>,
+    method: Method,
+    query: Option<String>,
+    headers: HeaderMap,
+}
+
+/// The outbound request produced by a register's script, wasm transform
+/// and templates.
+struct Rendered {
+    template_data: Map<String, Value>,
+    request_context: Value,
+    extra_headers: HashMap<String, String>,
+    encoded: EncodedBody,
+    target: RenderedTarget,
+}
+
+/// A target request, as rendered or as buffered while paused.
+struct Outbound {
+    method: reqwest::Method,
+    url: reqwest::Url,
+    /// The target's headers, then those added by the script or wasm transform
+    headers: Vec<(String, String)>,
+    content_type: String,
     body: String,
-) -> Result<Json<Value>, (StatusCode, Json<ErrorResponse>)> {
-    let endpoint = format!("/{}", path);
-    
+}
+
+impl Outbound {
+    fn rendered(rendered: &Rendered) -> Self {
+        let extra_headers = rendered.extra_headers.iter().map(|(k, v)| (k.clone(), v.clone()));
+        Self {
+            method: rendered.target.method.clone(),
+            url: rendered.target.url.clone(),
+            headers: rendered.target.headers.iter().cloned().chain(extra_headers).collect(),
+            content_type: rendered.encoded.content_type.to_string(),
+            body: rendered.encoded.body.clone(),
+        }
+    }
+
+    fn buffered(request: &BufferedRequest) -> Result<Self, String> {
+        Ok(Self {
+            method: reqwest::Method::from_bytes(request.method.as_bytes()).map_err(|e| e.to_string())?,
+            url: reqwest::Url::parse(&request.url).map_err(|e| e.to_string())?,
+            headers: request.headers.clone(),
+            content_type: request.content_type.clone(),
+            body: request.body.clone(),
+        })
+    }
+}
+
+/// Handle a webhook, filling in `record` once its body has been read and
+/// it is not a verification handshake.
+async fn process_webhook(
+    state: &AppState,
+    routes: &Routes,
+    inbound: &Inbound,
+    body: Body,
+    record: &mut Option<DeliveryRecord>,
+) -> Result<Response, (StatusCode, Json<ErrorResponse>)> {
+    let endpoint = &inbound.endpoint;
+    let inbound_headers = &inbound.headers;
+    let route = info_span!("webhook.verify").in_scope(|| verify_sender(routes, inbound))?;
+    let register = &route.register;
+
+    // Read and decompress the body within the register's size limit
+    let max_body_bytes = register.max_body_bytes.unwrap_or(state.max_body_bytes);
+    let content_length = inbound_headers
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok());
+    let content_encoding = inbound_headers
+        .get(axum::http::header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok());
+    let body = inbound_body::read_body(body, content_length, max_body_bytes, state.body_read_timeout)
+        .await
+        .and_then(|body| inbound_body::decompress(content_encoding, body, max_body_bytes))
+        .map_err(|e| {
+            warn!(endpoint = %endpoint, error = %e, "Rejected request body");
+            (e.status(), Json(ErrorResponse::new(e.to_string())))
+        })?;
+
     info!(
         endpoint = %endpoint,
         payload_size = body.len(),
         "Processing webhook request"
     );
 
-    // Find the matching register
-    let register = state.registers.get(&endpoint).ok_or_else(|| {
-        warn!(endpoint = %endpoint, "Webhook endpoint not found");
-        (
-            StatusCode::NOT_FOUND,
-            Json(ErrorResponse {
-                error: "Endpoint not found".to_string(),
-            }),
-        )
-    })?;
+    // Answer subscription verification handshakes without forwarding them
+    if let Some(handshake) = register.handshake {
+        if let Some(response) = handshake::answer(handshake, &inbound.method, inbound.query.as_deref(), &body) {
+            info!(endpoint = %endpoint, handshake = handshake.as_str(), "Answered verification handshake");
+            return Ok(response);
+        }
+    }
 
-    // Parse incoming JSON
-    let request_data: Value = serde_json::from_str(&body).map_err(|e| {
+    // Record the delivery from here on
+    let content_type = inbound_headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    let raw_body = String::from_utf8_lossy(&body).into_owned();
+    let record = record.insert(DeliveryRecord::new(
+        &inbound.request_id,
+        inbound.received_at,
+        endpoint,
+        delivery_log::InboundRecord {
+            method: inbound.method.to_string(),
+            source: inbound.client_ip.to_string(),
+            content_type: content_type.map(str::to_string),
+            body_bytes: body.len(),
+            headers: recorded_headers(inbound_headers),
+            body: Some(raw_body.clone()),
+        },
+    ));
+
+    // Decode the incoming payload according to its Content-Type
+    let request_data = inbound::decode_payload(content_type, &body).await.map_err(|e| {
         (
             StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: format!("Invalid JSON: {}", e),
-            }),
+            Json(ErrorResponse::new(e)),
         )
     })?;
 
-    // Convert JSON value to a map for template rendering
-    let template_data = json_to_template_data(&request_data);
+    let rendered = render_webhook(route, inbound, request_data, raw_body)
+        .instrument(info_span!("webhook.render"))
+        .await?;
+    let Some(mut rendered) = rendered else {
+        record.outcome = Outcome::Dropped;
+        return Ok(Json(serde_json::json!({ "status": "dropped" })).into_response());
+    };
+    record.rendered_sha256 = Some(format!("{:x}", Sha256::digest(rendered.encoded.body.as_bytes())));
+    record.rendered_body = Some(rendered.encoded.body.clone());
+
+    let outbound = Outbound::rendered(&rendered);
+    record.target = Some(delivery_log::TargetRecord {
+        method: outbound.method.to_string(),
+        url: span_url(&outbound.url),
+    });
+
+    // Hold the request back while its register or target is paused
+    let target_key = pause::target_key(&outbound.url);
+    let hold = state.pause.hold(endpoint, &target_key, || BufferedRequest {
+        id: 0,
+        target: target_key.clone(),
+        method: outbound.method.to_string(),
+        url: outbound.url.to_string(),
+        headers: outbound.headers.clone(),
+        content_type: outbound.content_type.clone(),
+        body: outbound.body.clone(),
+        record: buffered_record(record),
+    });
+    match hold {
+        Hold::Pass => {}
+        Hold::Buffered => {
+            info!(endpoint = %endpoint, target = %target_key, "Buffered webhook while paused");
+            record.outcome = Outcome::Buffered;
+            return Ok((StatusCode::ACCEPTED, Json(serde_json::json!({ "status": "buffered" }))).into_response());
+        }
+        Hold::Rejected(reason) => {
+            warn!(endpoint = %endpoint, target = %target_key, reason, "Rejected webhook while paused");
+            let status = StatusCode::from_u16(state.pause.reject_status()).unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+            return Err((status, Json(ErrorResponse::new(reason))));
+        }
+    }
 
-    // Render the template
-    let rendered_payload = state
-        .handlebars
-        .render(&register.template, &template_data)
+    let response = send_to_target(state, register, inbound_headers, &outbound, record)
+        .instrument(send_span(&outbound))
+        .await?;
+
+    // Answer the sender according to the register's response policy
+    rendered.template_data.insert("_request".to_string(), rendered.request_context);
+    route
+        .response
+        .respond(rendered.template_data, response)
+        .await
         .map_err(|e| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Template rendering failed: {}", e),
-                }),
+                Json(ErrorResponse::new(e)),
+            )
+        })
+}
+
+/// Check the sender's address and credentials before touching the payload,
+/// returning the route for the endpoint.
+fn verify_sender<'a>(
+    routes: &'a Routes,
+    inbound: &Inbound,
+) -> Result<&'a Route, (StatusCode, Json<ErrorResponse>)> {
+    let endpoint = &inbound.endpoint;
+    let client_ip = inbound.client_ip;
+
+    // Reject senders outside the endpoint's allowed source ranges
+    if !routes.sources.allows(endpoint, client_ip) {
+        warn!(endpoint = %endpoint, client_ip = %client_ip, "Source address not allowed");
+        return Err((
+            StatusCode::FORBIDDEN,
+            Json(ErrorResponse::new("Source address not allowed")),
+        ));
+    }
+
+    // Find the matching register
+    let route = routes.get(endpoint).ok_or_else(|| {
+        warn!(endpoint = %endpoint, "Webhook endpoint not found");
+        (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new("Endpoint not found")),
+        )
+    })?;
+    if !route.register.enabled {
+        warn!(endpoint = %endpoint, "Webhook endpoint disabled");
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new("Endpoint disabled")),
+        ));
+    }
+
+    if let Some(authenticator) = &route.authenticator {
+        authenticator.check(&inbound.headers, inbound.query.as_deref()).map_err(|reason| {
+            warn!(endpoint = %endpoint, reason, "Inbound authentication failed");
+            (
+                StatusCode::UNAUTHORIZED,
+                Json(ErrorResponse::new("Unauthorized")),
             )
         })?;
+    }
+
+    Ok(route)
+}
+
+/// Run the register's script, wasm transform and templates over the decoded
+/// payload. Returns `None` when the event was dropped.
+async fn render_webhook(
+    route: &Route,
+    inbound: &Inbound,
+    request_data: Value,
+    raw_body: String,
+) -> Result<Option<Rendered>, (StatusCode, Json<ErrorResponse>)> {
+    let endpoint = inbound.endpoint.as_str();
+    let register = &route.register;
+
+    // Run the register's script, which may rewrite the payload, add headers,
+    // reroute the request or drop the event entirely
+    let request_context = request_context(endpoint, &inbound.method, &inbound.headers, &raw_body);
+    let mut target_url_override = None;
+    let mut extra_headers = HashMap::new();
+    let request_data = match &route.script {
+        Some(script) => {
+            let script = script.clone();
+            let request_context = request_context.clone();
+            let current_target = register.target.url.clone();
+            let outcome = tokio::task::spawn_blocking(move || {
+                script.run(&request_context, &request_data, &current_target)
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result)
+            .map_err(|e| {
+                warn!(endpoint = %endpoint, error = %e, "Webhook script failed");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::new(format!("Script execution failed: {}", e))),
+                )
+            })?;
+
+            if outcome.drop {
+                info!(endpoint = %endpoint, "Webhook dropped by script");
+                return Ok(None);
+            }
+
+            if outcome.target_url != register.target.url {
+                target_url_override = Some(outcome.target_url);
+            }
+            extra_headers = outcome.headers;
+            outcome.payload
+        }
+        None => request_data,
+    };
+
+    // Convert JSON value to a map for template rendering, keeping the raw
+    // body available as `_raw`
+    let mut template_data = json_to_template_data(&request_data);
+    template_data.insert("_raw".to_string(), Value::String(raw_body));
 
-    // Parse the rendered payload as JSON to validate it
-    let payload_json: Value = serde_json::from_str(&rendered_payload).map_err(|e| {
+    // Produce the outbound body, either from the register's wasm transform
+    // or by rendering its template
+    let rendered_payload = match &route.wasm {
+        Some(transform) => {
+            let transform = transform.clone();
+            let request_context = request_context.clone();
+            let output = tokio::task::spawn_blocking(move || {
+                transform.run(&request_context, &request_data)
+            })
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|result| result)
+            .map_err(|e| {
+                warn!(endpoint = %endpoint, error = %e, "Wasm transform failed");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::new(format!("Wasm transform failed: {}", e))),
+                )
+            })?;
+
+            if output.drop {
+                info!(endpoint = %endpoint, "Webhook dropped by wasm transform");
+                return Ok(None);
+            }
+
+            extra_headers.extend(output.headers);
+            output.body
+        }
+        None => route
+            .render_template(&template_data)
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::new(format!("Template rendering failed: {}", e))),
+                )
+            })?,
+    };
+
+    // Validate and serialize the rendered payload for the target's body format
+    let encoded = encode_body(register.target.body_format, &rendered_payload).map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: format!("Rendered template is not valid JSON: {}", e),
-            }),
+            Json(ErrorResponse::new(e)),
         )
     })?;
 
-    // Send request to target
-    let method = register.target.method.to_uppercase();
-    let request_builder = match method.as_str() {
-        "GET" => state.http_client.get(&register.target.url),
-        "POST" => state.http_client.post(&register.target.url),
-        "PUT" => state.http_client.put(&register.target.url),
-        "DELETE" => state.http_client.delete(&register.target.url),
-        "PATCH" => state.http_client.patch(&register.target.url),
-        _ => {
-            return Err((
+    // Render the target URL, method and headers with the same context
+    let target = route
+        .target
+        .render(&template_data, target_url_override.as_deref())
+        .map_err(|e| {
+            warn!(endpoint = %endpoint, error = %e, "Target rendering failed");
+            (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Unsupported HTTP method: {}", method),
-                }),
-            ))
-        }
-    };
+                Json(ErrorResponse::new(e)),
+            )
+        })?;
+
+    Ok(Some(Rendered {
+        template_data,
+        request_context,
+        extra_headers,
+        encoded,
+        target,
+    }))
+}
+
+/// Send the rendered request to the target, retrying per the register's
+/// `retry_config`, and note the target, status and attempts in `record`.
+/// Runs in the `webhook.send` span, whose trace context is forwarded to the
+/// target.
+async fn send_to_target(
+    state: &AppState,
+    register: &WebhookRegister,
+    inbound_headers: &HeaderMap,
+    target: &Outbound,
+    record: &mut DeliveryRecord,
+) -> Result<reqwest::Response, (StatusCode, Json<ErrorResponse>)> {
+    let endpoint = &register.endpoint;
+
+    // Use the client for the target's TLS/proxy settings
+    let prepared = state
+        .clients
+        .prepare(&register.target, target.url.clone())
+        .await
+        .map_err(|e| {
+            warn!(endpoint = %endpoint, error = %e, "Target request not prepared");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(ErrorResponse::new(e)),
+            )
+        })?;
+    let request_builder = prepared.client.request(target.method.clone(), prepared.url);
 
+    let trace_headers = telemetry::trace_headers(&tracing::Span::current(), inbound_headers);
     let mut headers = reqwest::header::HeaderMap::new();
-    for (key, value) in &register.target.headers {
+
+    // Forward the request ID unless the target's own headers set it
+    let request_id = inbound_headers
+        .get(request_id::REQUEST_ID_HEADER)
+        .and_then(|v| reqwest::header::HeaderValue::from_bytes(v.as_bytes()).ok());
+    if let (Some(name), Some(id)) = (&state.request_id_header, request_id) {
+        headers.insert(name.clone(), id);
+    }
+    for (key, value) in target
+        .headers
+        .iter()
+        .map(|(k, v)| (k, v))
+        .chain(trace_headers.iter())
+    {
         let header_name = reqwest::header::HeaderName::from_bytes(key.as_bytes())
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: format!("Invalid header name: {}: {}", key, e) })))?;
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse::new(format!("Invalid header name: {}: {}", key, e)))))?;
         let header_value = reqwest::header::HeaderValue::from_str(value)
-            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { error: format!("Invalid header value for {}: {}", key, e) })))?;
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse::new(format!("Invalid header value for {}: {}", key, e)))))?;
         headers.insert(header_name, header_value);
     }
     if !headers.contains_key(reqwest::header::CONTENT_TYPE) {
-        headers.insert(reqwest::header::CONTENT_TYPE, "application/json".parse().unwrap());
+        headers.insert(
+            reqwest::header::CONTENT_TYPE,
+            reqwest::header::HeaderValue::from_str(&target.content_type)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse::new(format!("Invalid content type: {}", e)))))?,
+        );
+    }
+    if let Some(host) = prepared.host_header {
+        let host = reqwest::header::HeaderValue::from_str(&host)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse::new(format!("Invalid host header: {}", e)))))?;
+        headers.insert(reqwest::header::HOST, host);
     }
 
-    let response = request_builder
-        .headers(headers)
-        .json(&payload_json)
-        .send()
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse {
-                    error: format!("Failed to send request to target: {}", e),
-                }),
-            )
-        })?;
+    let request_builder = request_builder.headers(headers).body(target.body.clone());
+    let started = std::time::Instant::now();
+    let retry = register.retry_config.as_ref().unwrap_or(&state.default_retry);
+    let (result, attempts) = delivery::send_with_retries(
+        &state.token_cache,
+        register.target.auth.as_ref(),
+        retry,
+        &target.method,
+        request_builder,
+    )
+    .await;
+    record.latency_ms = started.elapsed().as_millis() as u64;
+    record.attempts = attempts;
+    record.status = result.as_ref().ok().map(|response| response.status().as_u16());
 
-    // Get response body
-    let response_text = response.text().await.map_err(|e| {
+    result.map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: format!("Failed to read response: {}", e),
-            }),
+            Json(ErrorResponse::new(format!("Failed to send request to target: {}", e))),
         )
-    })?;
+    })
+}
 
-    // Try to parse response as JSON, if it fails, return as string
-    let response_json = serde_json::from_str::<Value>(&response_text)
-        .unwrap_or_else(|_| Value::String(response_text));
+fn send_span(target: &Outbound) -> tracing::Span {
+    info_span!(
+        "webhook.send",
+        otel.kind = "client",
+        target.url = %span_url(&target.url),
+        http.method = %target.method,
+    )
+}
 
-    Ok(Json(serde_json::json!({
-        "status": "success",
-        "target_response": response_json
-    })))
+/// The delivery record kept with a buffered request, without the sender's
+/// credentials, which may end up on disk.
+fn buffered_record(record: &DeliveryRecord) -> DeliveryRecord {
+    let mut buffered = record.clone();
+    buffered.inbound.headers =
+        redaction::current().headers(record.inbound.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+    buffered
 }
 
-// New handler for debug endpoint
-async fn handle_debug_request(
-    headers: axum::http::HeaderMap,
-    body: String,
-) -> Result<Json<Value>, (StatusCode, Json<ErrorResponse>)> {
-    tracing::info!("Debug request headers: {:?}", headers);
-    tracing::info!("Debug request payload: {}", body);
-    Ok(Json(
-        serde_json::json!({"status": "success", "message": "Payload logged"}),
-    ))
+/// Send buffered requests as their registers and targets are resumed, in
+/// the order they arrived.
+async fn drain_paused(state: AppState) {
+    loop {
+        while let Some(request) = state.pause.next_ready() {
+            let span = info_span!(
+                "webhook.resume",
+                request_id = %request.record.request_id,
+                endpoint = %request.record.endpoint,
+            );
+            send_buffered(&state, &request).instrument(span).await;
+            state.pause.complete(request.id);
+        }
+        state.pause.ready().await;
+    }
+}
+
+async fn send_buffered(state: &AppState, request: &BufferedRequest) {
+    let mut record = request.record.clone();
+    let routes = state.routes.current();
+
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&record.request_id) {
+        headers.insert(request_id::REQUEST_ID_HEADER, value);
+    }
+    let result = match (routes.get(&record.endpoint), Outbound::buffered(request)) {
+        (None, _) => Err("Register removed while paused".to_string()),
+        (Some(route), Ok(outbound)) => send_to_target(state, &route.register, &headers, &outbound, &mut record)
+            .instrument(send_span(&outbound))
+            .await
+            .map(|_| ())
+            .map_err(|(_, Json(e))| e.error),
+        (Some(_), Err(e)) => Err(format!("Invalid buffered request: {}", e)),
+    };
+    match &result {
+        Ok(()) => info!(endpoint = %record.endpoint, status = ?record.status, "Sent buffered request"),
+        Err(e) => warn!(endpoint = %record.endpoint, error = %e, "Failed to send buffered request"),
+    }
+
+    // Settled afresh, as the buffered record was already logged
+    record.timestamp = chrono::Utc::now();
+    record.outcome = Outcome::Failed;
+    record.finish(result.err());
+    state.tail.publish(&record);
+    if let Some(log) = &state.delivery_log {
+        log.record(record);
+    }
+}
+
+/// The target URL as recorded on spans and delivery records, without
+/// credentials, query or fragment.
+fn span_url(url: &reqwest::Url) -> String {
+    let mut url = url.clone();
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.set_query(None);
+    url.set_fragment(None);
+    url.to_string()
+}
+
+/// Inbound headers for a delivery record, which redacts them when stored.
+fn recorded_headers(headers: &HeaderMap) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+/// Request metadata exposed to scripts and wasm transforms as `request`.
+fn request_context(endpoint: &str, method: &Method, headers: &HeaderMap, body: &str) -> Value {
+    let headers: Map<String, Value> = headers
+        .iter()
+        .filter_map(|(name, value)| {
+            value
+                .to_str()
+                .ok()
+                .map(|v| (name.as_str().to_string(), Value::String(v.to_string())))
+        })
+        .collect();
+
+    serde_json::json!({
+        "endpoint": endpoint,
+        "method": method.as_str(),
+        "headers": headers,
+        "body": body,
+    })
 }
 
 pub fn json_to_template_data(value: &Value) -> Map<String, Value> {
@@ -218,44 +773,40 @@ pub fn json_to_template_data(value: &Value) -> Map<String, Value> {
     }
 }
 
-fn init_logging(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+fn init_logging(args: &Args) -> Result<Option<SdkTracerProvider>, Box<dyn std::error::Error>> {
     let env_filter = EnvFilter::try_from_default_env()
         .or_else(|_| EnvFilter::try_new(&args.log_level))?;
 
+    // Export spans over OTLP when a collector is configured
+    let provider = args
+        .otlp_endpoint
+        .as_deref()
+        .map(|endpoint| telemetry::tracer_provider(endpoint, &args.otel_service_name))
+        .transpose()?;
+    let otel_layer = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer("hermes-rs")));
+
     match args.log_format.as_str() {
         "json" => {
             tracing_subscriber::registry()
                 .with(env_filter)
-                .with(tracing_subscriber::fmt::layer().json())
+                .with(otel_layer)
+                .with(tracing_subscriber::fmt::layer().json().with_writer(secrets::RedactingMakeWriter))
                 .init();
         }
         _ => {
             tracing_subscriber::registry()
                 .with(env_filter)
-                .with(tracing_subscriber::fmt::layer().pretty())
+                .with(otel_layer)
+                .with(tracing_subscriber::fmt::layer().pretty().with_writer(secrets::RedactingMakeWriter))
                 .init();
         }
     }
 
-    Ok(())
-}
-
-fn escape_newlines_helper(
-    h: &Helper,
-    _: &Handlebars,
-    _: &Context,
-    _: &mut RenderContext,
-    out: &mut dyn Output,
-) -> HelperResult {
-    if let Some(param) = h.param(0) {
-        let raw = param.value().as_str().unwrap_or("");
-        let escaped = raw.replace('\n', "\\n");
-        out.write(&escaped)?;
-    }
-    Ok(())
+    Ok(provider)
 }
 
-
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Load environment variables from .env file if present
@@ -264,8 +815,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Parse command line arguments and environment variables
     let args = Args::parse();
 
-    // Initialize structured logging
-    init_logging(&args)?;
+    // Initialize structured logging and trace export
+    let tracer_provider = init_logging(&args)?;
 
     info!(
         service = "hermes-rs",
@@ -274,7 +825,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
 
     // Load configuration
-    let config = Config::load(&args.config).await?;
+    let (config, source) = Config::load_with_source(&args.config).await?;
     info!(
         config_path = %args.config.display(),
         webhook_count = config.registers.len(),
@@ -293,20 +844,90 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     // Create application state
-    let state = AppState::new(config, &args);
+    let admin = config.settings.admin.clone();
+    let readiness_config = config.settings.readiness.clone();
+    let debug_bin = Arc::new(
+        DebugBin::new(&config.settings.debug).unwrap_or_else(|e| panic!("Invalid debug settings: {}", e)),
+    );
+    let state = AppState::new(config, &source, &args);
+    state.routes.clone().spawn_reload_on_hangup();
+    tokio::spawn(drain_paused(state.clone()));
+    let delivery_log = state.delivery_log.clone();
+    let tail = state.tail.clone();
+    let clients = state.clients.clone();
+    let routes = state.routes.clone();
+    let pause = state.pause.clone();
+    let pause_buffer = state.pause.clone();
+    let readiness = Arc::new(health::Readiness::new(state.routes.clone(), &readiness_config, delivery_log.clone()));
 
     // Build the router with health checks
     let mut app = Router::new()
         .route("/*path", any(handle_webhook))
-        .route("/debug", axum::routing::post(handle_debug_request))
+        .merge(debug_bin::router(debug_bin.clone()))
         .layer(ServiceBuilder::new().layer(TraceLayer::new_for_http()))
         .with_state(state);
 
+    // TLS settings shared by the main and admin listeners
+    let tls = match tls::ListenerTls::from_args(&args) {
+        Some(listener_tls) => {
+            let rustls_config = RustlsConfig::from_config(Arc::new(listener_tls.server_config()?));
+            info!(
+                cert = %listener_tls.cert.display(),
+                client_auth = listener_tls.client_ca.is_some(),
+                "TLS enabled on listener"
+            );
+            if args.tls_reload_interval > 0 {
+                listener_tls.spawn_reloader(rustls_config.clone(), Duration::from_secs(args.tls_reload_interval));
+            }
+            Some(rustls_config)
+        }
+        None => None,
+    };
+    let header_read_timeout = Duration::from_secs(args.header_read_timeout);
+
+    // Serve the admin API only when it has credentials configured, on the
+    // main listener unless it has an address of its own
+    let admin_handle = axum_server::Handle::new();
+    if let Some(admin) = admin {
+        let auth = InboundAuthenticator::compile(&admin.auth)
+            .unwrap_or_else(|e| panic!("Invalid admin auth settings: {}", e));
+        let admin_router = admin_api::router(admin_api::AdminState {
+            auth: Arc::new(auth),
+            delivery_log: delivery_log.clone(),
+            tail,
+            routes,
+            pause,
+            debug_bin,
+        });
+        match &admin.bind_address {
+            Some(address) => {
+                let address: SocketAddr = address
+                    .parse()
+                    .unwrap_or_else(|e| panic!("Invalid admin bind address {}: {}", address, e));
+                let admin_server = axum_server::bind(address).handle(admin_handle.clone());
+                let tls = tls.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(admin_server, tls, header_read_timeout, admin_router).await {
+                        warn!(error = %e, "Admin API listener failed");
+                    }
+                });
+                info!(address = %address, "Admin API enabled on its own listener");
+            }
+            None => {
+                app = app.merge(admin_router);
+                info!("Admin API enabled");
+            }
+        }
+    } else if delivery_log.is_some() {
+        info!("Recording deliveries; configure settings.admin to query them over HTTP");
+    }
+
     // Add health check endpoints if enabled
     if args.health_check_enabled {
         app = app
             .route("/health", get(health::health_check))
-            .route("/ready", get(health::readiness_check));
+            .route("/ready", get(health::readiness_check).with_state(readiness.clone()));
+        readiness.clone().spawn_probes(clients);
         info!("Health check endpoints enabled");
     }
 
@@ -321,17 +942,68 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
 
     let listener = TcpListener::bind(&addr).await?;
-    
+
+    let server = axum_server::from_tcp(listener.into_std()?);
+    let handle = axum_server::Handle::new();
+    let shutdown_handle = handle.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        readiness.start_draining();
+        if readiness_config.drain_delay_seconds > 0 {
+            info!(seconds = readiness_config.drain_delay_seconds, "Draining before shutdown");
+            tokio::time::sleep(Duration::from_secs(readiness_config.drain_delay_seconds)).await;
+        }
+        shutdown_handle.graceful_shutdown(Some(Duration::from_secs(30)));
+        admin_handle.graceful_shutdown(Some(Duration::from_secs(30)));
+    });
     info!("Webhook proxy server is ready to accept connections");
-    
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    serve(server.handle(handle), tls, header_read_timeout, app).await?;
+
+    // Write out delivery records still queued
+    if let Some(log) = delivery_log {
+        tokio::task::spawn_blocking(move || log.flush()).await?;
+    }
+
+    // Write out changes to the pause buffer still queued
+    tokio::task::spawn_blocking(move || pause_buffer.flush()).await?;
+
+    // Flush spans still waiting in the batch exporter
+    if let Some(provider) = tracer_provider {
+        if let Ok(Err(e)) = tokio::task::spawn_blocking(move || provider.shutdown()).await {
+            warn!(error = %e, "Failed to flush traces");
+        }
+    }
 
     info!("Server shutdown complete");
     Ok(())
 }
 
+/// Serve `app`, over TLS when configured. Both plain and TLS listeners are
+/// served by axum-server, which limits how long clients may take to send
+/// their request headers.
+async fn serve(
+    mut server: axum_server::Server,
+    tls: Option<RustlsConfig>,
+    header_read_timeout: Duration,
+    app: Router,
+) -> std::io::Result<()> {
+    server
+        .http_builder()
+        .http1()
+        .timer(hyper_util::rt::TokioTimer::new())
+        .header_read_timeout(header_read_timeout);
+    let service = app.into_make_service_with_connect_info::<SocketAddr>();
+    match tls {
+        Some(config) => {
+            server
+                .acceptor(axum_server::tls_rustls::RustlsAcceptor::new(config))
+                .serve(service)
+                .await
+        }
+        None => server.serve(service).await,
+    }
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         tokio::signal::ctrl_c()
@@ -378,6 +1050,46 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_buffered_request_for_removed_register_is_recorded_failed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("deliveries.jsonl");
+        let source: serde_yaml::Value = serde_yaml::from_str(&format!(
+            "registers: []\nsettings:\n  delivery_log:\n    path: {}\n    store_bodies: false\n",
+            path.display()
+        ))
+        .unwrap();
+        let config: Config = serde_yaml::from_value(source.clone()).unwrap();
+        let state = AppState::new(config, &source, &Args::parse_from(["hermes-rs"]));
+
+        let inbound = delivery_log::InboundRecord {
+            method: "POST".to_string(),
+            source: "127.0.0.1".to_string(),
+            content_type: None,
+            body_bytes: 0,
+            headers: Default::default(),
+            body: None,
+        };
+        let request = BufferedRequest {
+            id: 1,
+            target: "https://chat.example.com".to_string(),
+            method: "POST".to_string(),
+            url: "https://chat.example.com/hooks".to_string(),
+            headers: Vec::new(),
+            content_type: "application/json".to_string(),
+            body: "{}".to_string(),
+            record: DeliveryRecord::new("req-1", chrono::Utc::now(), "/webhook/gone", inbound),
+        };
+        send_buffered(&state, &request).await;
+        state.delivery_log.as_ref().unwrap().flush();
+
+        let logged = std::fs::read_to_string(&path).unwrap();
+        let record: Value = serde_json::from_str(logged.lines().last().unwrap()).unwrap();
+        assert_eq!(record["endpoint"], "/webhook/gone");
+        assert_eq!(record["outcome"], "failed");
+        assert_eq!(record["error"], "Register removed while paused");
+    }
+
     #[tokio::test]
     async fn test_config_loading() {
         // This would require a test config file
@@ -0,0 +1,501 @@
+//! Pausing registers and targets.
+//!
+//! A register is paused by its endpoint, a target by its origin
+//! (`https://host:port`). While either is paused, webhooks for it are
+//! rendered and buffered, or with `settings.pause.mode: reject` answered
+//! with `settings.pause.reject_status`. The buffer is bounded by
+//! `buffer_capacity`; with `buffer_path` it is kept on disk together with
+//! what is paused, so both survive a restart.
+//!
+//! Once nothing holds them, buffered requests are sent in the order they
+//! arrived. Until then, later webhooks for the same register or target are
+//! buffered behind them.
+//!
+//! Buffered requests are kept as rendered, so `${...}` secrets resolved into
+//! target URLs and headers are in `buffer_path` in plain text. The file is
+//! created readable by its owner only. Target `auth` credentials are applied
+//! when the request is sent and never written.
+
+use crate::config::{PauseConfig, PauseMode};
+use crate::delivery_log::DeliveryRecord;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender, SyncSender};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+/// What a pause applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    /// A register, by endpoint
+    Register(String),
+    /// Every register sending to a target, by URL or origin
+    Target(String),
+}
+
+/// The origin a target is paused by.
+pub fn target_key(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+/// A rendered target request waiting for a resume.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BufferedRequest {
+    #[serde(default)]
+    pub id: u64,
+    /// The target's origin
+    pub target: String,
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub content_type: String,
+    pub body: String,
+    /// The delivery record as of buffering, with inbound headers redacted
+    pub record: DeliveryRecord,
+}
+
+/// What happened to a webhook offered to [`PauseControl::hold`].
+pub enum Hold {
+    /// Nothing is paused; send it now
+    Pass,
+    Buffered,
+    /// Paused in reject mode, or the buffer is full
+    Rejected(&'static str),
+}
+
+/// A line of the buffer file: a buffered request, or the id of one sent since.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Line {
+    Done { done: u64 },
+    Request(Box<BufferedRequest>),
+}
+
+enum Message {
+    Line(Line),
+    Flush(SyncSender<()>),
+}
+
+/// Writes the buffer file on its own thread. Sent requests are marked with a
+/// `done` line, and the file is rewritten with only the requests still
+/// buffered once most of its lines are stale.
+struct Writer {
+    path: PathBuf,
+    buffered: BTreeMap<u64, BufferedRequest>,
+    lines: usize,
+}
+
+impl Writer {
+    fn write(&mut self, line: Line) -> std::io::Result<()> {
+        match &line {
+            Line::Request(request) => {
+                self.buffered.insert(request.id, (**request).clone());
+            }
+            Line::Done { done } => {
+                self.buffered.remove(done);
+            }
+        }
+        if self.lines >= (self.buffered.len() * 2).max(COMPACT_MIN_LINES) {
+            return self.compact();
+        }
+        let mut file = open_buffer(&self.path, true)?;
+        writeln!(file, "{}", serde_json::to_string(&line).map_err(std::io::Error::other)?)?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    fn compact(&mut self) -> std::io::Result<()> {
+        let mut temp = self.path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let mut file = open_buffer(Path::new(&temp), false)?;
+        for request in self.buffered.values() {
+            writeln!(file, "{}", serde_json::to_string(request).map_err(std::io::Error::other)?)?;
+        }
+        std::fs::rename(&temp, &self.path)?;
+        self.lines = self.buffered.len();
+        Ok(())
+    }
+
+    fn spawn(mut self) -> Result<Sender<Message>, String> {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::Builder::new()
+            .name("pause-buffer".to_string())
+            .spawn(move || {
+                for message in receiver {
+                    match message {
+                        Message::Line(line) => {
+                            if let Err(e) = self.write(line) {
+                                warn!(path = %self.path.display(), error = %e, "Failed to persist pause buffer");
+                            }
+                        }
+                        Message::Flush(done) => {
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            })
+            .map_err(|e| format!("Failed to start pause buffer writer: {}", e))?;
+        Ok(sender)
+    }
+}
+
+/// Stale lines tolerated in the buffer file before it is compacted.
+const COMPACT_MIN_LINES: usize = 64;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Paused {
+    registers: BTreeSet<String>,
+    targets: BTreeSet<String>,
+}
+
+struct State {
+    paused: Paused,
+    queue: VecDeque<BufferedRequest>,
+    next_id: u64,
+}
+
+pub struct PauseControl {
+    config: PauseConfig,
+    state: Mutex<State>,
+    ready: Notify,
+    writer: Option<Mutex<Sender<Message>>>,
+}
+
+impl PauseControl {
+    /// Reload what was paused and buffered from `buffer_path`, if set.
+    pub fn new(config: &PauseConfig) -> Result<Self, String> {
+        axum::http::StatusCode::from_u16(config.reject_status)
+            .map_err(|_| format!("Invalid pause reject status {}", config.reject_status))?;
+
+        let mut paused = Paused::default();
+        let mut buffered = BTreeMap::new();
+        let mut writer = None;
+        if let Some(path) = &config.buffer_path {
+            match std::fs::read_to_string(paused_path(path)) {
+                Ok(contents) => {
+                    paused = serde_json::from_str(&contents).map_err(|e| format!("Invalid pause state: {}", e))?
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Failed to read pause state: {}", e)),
+            }
+            match std::fs::File::open(path) {
+                Ok(file) => {
+                    restrict_permissions(path)?;
+                    for line in BufReader::new(file).lines().map_while(Result::ok) {
+                        match serde_json::from_str::<Line>(&line) {
+                            Ok(Line::Request(request)) => {
+                                buffered.insert(request.id, *request);
+                            }
+                            Ok(Line::Done { done }) => {
+                                buffered.remove(&done);
+                            }
+                            Err(e) => warn!(path = %path.display(), error = %e, "Skipping unreadable buffered request"),
+                        }
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+            }
+            let mut file = Writer {
+                path: path.clone(),
+                buffered: buffered.clone(),
+                lines: 0,
+            };
+            file.compact().map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+            writer = Some(Mutex::new(file.spawn()?));
+        }
+        let queue: VecDeque<_> = buffered.into_values().collect();
+        if !queue.is_empty() {
+            info!(buffered = queue.len(), "Reloaded buffered requests");
+        }
+        let next_id = queue.iter().map(|r| r.id + 1).max().unwrap_or(1);
+
+        let control = Self {
+            config: config.clone(),
+            state: Mutex::new(State { paused, queue, next_id }),
+            ready: Notify::new(),
+            writer,
+        };
+        // Anything no longer paused can go right away
+        control.ready.notify_one();
+        Ok(control)
+    }
+
+    pub fn reject_status(&self) -> u16 {
+        self.config.reject_status
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn pause(&self, scope: Scope) -> Result<Scope, String> {
+        let scope = normalize(scope)?;
+        let mut state = self.lock();
+        match &scope {
+            Scope::Register(endpoint) => state.paused.registers.insert(endpoint.clone()),
+            Scope::Target(origin) => state.paused.targets.insert(origin.clone()),
+        };
+        self.save_paused(&state.paused);
+        info!(scope = ?scope, "Paused");
+        Ok(scope)
+    }
+
+    /// Resume `scope`, sending what it held back.
+    pub fn resume(&self, scope: Scope) -> Result<Scope, String> {
+        let scope = normalize(scope)?;
+        let mut state = self.lock();
+        match &scope {
+            Scope::Register(endpoint) => state.paused.registers.remove(endpoint),
+            Scope::Target(origin) => state.paused.targets.remove(origin),
+        };
+        self.save_paused(&state.paused);
+        info!(scope = ?scope, buffered = state.queue.len(), "Resumed");
+        self.ready.notify_one();
+        Ok(scope)
+    }
+
+    /// Decide on a webhook for `endpoint` sent to `target`, buffering the
+    /// request `make` builds if it has to wait.
+    pub fn hold(&self, endpoint: &str, target: &str, make: impl FnOnce() -> BufferedRequest) -> Hold {
+        let mut state = self.lock();
+        let paused = state.paused.registers.contains(endpoint) || state.paused.targets.contains(target);
+        let queued = state.queue.iter().any(|r| r.record.endpoint == endpoint || r.target == target);
+        if !paused && !queued {
+            return Hold::Pass;
+        }
+        if paused && self.config.mode == PauseMode::Reject {
+            return Hold::Rejected("Endpoint paused");
+        }
+        if state.queue.len() >= self.config.buffer_capacity {
+            return Hold::Rejected("Pause buffer full");
+        }
+
+        let mut request = make();
+        request.id = state.next_id;
+        state.next_id += 1;
+        self.persist(Line::Request(Box::new(request.clone())));
+        state.queue.push_back(request);
+        // A resume may have come in between the check and the push
+        self.ready.notify_one();
+        Hold::Buffered
+    }
+
+    /// The oldest buffered request that may be sent now: neither its register
+    /// nor its target is paused, and nothing earlier for either still waits.
+    pub fn next_ready(&self) -> Option<BufferedRequest> {
+        let state = self.lock();
+        let mut blocked_endpoints = HashSet::new();
+        let mut blocked_targets = HashSet::new();
+        for request in &state.queue {
+            let endpoint = request.record.endpoint.as_str();
+            let held = state.paused.registers.contains(endpoint)
+                || state.paused.targets.contains(&request.target)
+                || blocked_endpoints.contains(endpoint)
+                || blocked_targets.contains(request.target.as_str());
+            if !held {
+                return Some(request.clone());
+            }
+            blocked_endpoints.insert(endpoint);
+            blocked_targets.insert(request.target.as_str());
+        }
+        None
+    }
+
+    /// Remove a request once it was sent.
+    pub fn complete(&self, id: u64) {
+        let mut state = self.lock();
+        state.queue.retain(|r| r.id != id);
+        self.persist(Line::Done { done: id });
+    }
+
+    /// Queue a change to the buffer file for the writer thread. Called with
+    /// the state locked, so the file sees changes in the queue's order.
+    fn persist(&self, line: Line) {
+        if let Some(writer) = &self.writer {
+            let _ = writer.lock().unwrap_or_else(|e| e.into_inner()).send(Message::Line(line));
+        }
+    }
+
+    /// Wait until every change to the buffer so far has been written.
+    pub fn flush(&self) {
+        let Some(writer) = &self.writer else { return };
+        let (done, wait) = mpsc::sync_channel(1);
+        if writer.lock().unwrap_or_else(|e| e.into_inner()).send(Message::Flush(done)).is_ok() {
+            let _ = wait.recv();
+        }
+    }
+
+    /// Wait until a resume or a new buffered request may have made one ready.
+    pub async fn ready(&self) {
+        self.ready.notified().await
+    }
+
+    /// What is paused and how much is buffered, for the admin API.
+    pub fn status(&self) -> serde_json::Value {
+        let state = self.lock();
+        serde_json::json!({
+            "mode": self.config.mode,
+            "registers": state.paused.registers,
+            "targets": state.paused.targets,
+            "buffered": state.queue.len(),
+            "buffer_capacity": self.config.buffer_capacity,
+        })
+    }
+
+    fn save_paused(&self, paused: &Paused) {
+        if let Some(path) = &self.config.buffer_path {
+            let written = serde_json::to_string(paused)
+                .map_err(std::io::Error::other)
+                .and_then(|json| std::fs::write(paused_path(path), json));
+            if let Err(e) = written {
+                warn!(error = %e, "Failed to persist pause state");
+            }
+        }
+    }
+}
+
+/// Open the buffer for appending or rewriting, creating it readable by its
+/// owner only.
+fn open_buffer(path: &Path, append: bool) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.create(true).write(true).append(append).truncate(!append);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+/// Tighten a buffer file created before it held secrets, or by hand.
+fn restrict_permissions(path: &Path) -> Result<(), String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to restrict {}: {}", path.display(), e))?;
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+/// Where what is paused is kept, next to the buffer.
+fn paused_path(buffer_path: &Path) -> PathBuf {
+    let mut path = buffer_path.as_os_str().to_owned();
+    path.push(".paused");
+    PathBuf::from(path)
+}
+
+fn normalize(scope: Scope) -> Result<Scope, String> {
+    match scope {
+        Scope::Register(endpoint) if endpoint.starts_with('/') => Ok(Scope::Register(endpoint)),
+        Scope::Register(endpoint) => Ok(Scope::Register(format!("/{}", endpoint))),
+        Scope::Target(url) => crate::target::validate_url(&url).map(|url| Scope::Target(target_key(&url))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery_log::InboundRecord;
+
+    fn request(endpoint: &str, target: &str) -> BufferedRequest {
+        let inbound = InboundRecord {
+            method: "POST".to_string(),
+            source: "127.0.0.1".to_string(),
+            content_type: None,
+            body_bytes: 0,
+            headers: Default::default(),
+            body: None,
+        };
+        BufferedRequest {
+            id: 0,
+            target: target.to_string(),
+            method: "POST".to_string(),
+            url: format!("{}/hooks", target),
+            headers: Vec::new(),
+            content_type: "application/json".to_string(),
+            body: "{}".to_string(),
+            record: DeliveryRecord::new("req", chrono::Utc::now(), endpoint, inbound),
+        }
+    }
+
+    #[test]
+    fn test_buffered_requests_drain_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = PauseConfig {
+            buffer_capacity: 3,
+            buffer_path: Some(dir.path().join("buffer.jsonl")),
+            ..Default::default()
+        };
+        let control = PauseControl::new(&config).unwrap();
+        let chat = "https://chat.example.com";
+
+        assert!(matches!(control.hold("/a", chat, || request("/a", chat)), Hold::Pass));
+        control.pause(Scope::Target("https://chat.example.com/hooks".to_string())).unwrap();
+        control.pause(Scope::Register("b".to_string())).unwrap();
+        for endpoint in ["/a", "/b", "/a"] {
+            assert!(matches!(control.hold(endpoint, chat, || request(endpoint, chat)), Hold::Buffered));
+        }
+        assert!(matches!(control.hold("/c", chat, || request("/c", chat)), Hold::Rejected(_)));
+        assert!(control.next_ready().is_none());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(config.buffer_path.as_ref().unwrap()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // The buffer and what is paused survive a restart
+        control.flush();
+        let control = PauseControl::new(&config).unwrap();
+        control.resume(Scope::Target(chat.to_string())).unwrap();
+        let first = control.next_ready().unwrap();
+        assert_eq!(first.record.endpoint, "/a");
+        control.complete(first.id);
+        // /b is still paused, and the second /a shares its target
+        assert!(control.next_ready().is_none());
+
+        control.resume(Scope::Register("/b".to_string())).unwrap();
+        let order: Vec<_> = std::iter::from_fn(|| {
+            let next = control.next_ready()?;
+            control.complete(next.id);
+            Some(next.record.endpoint)
+        })
+        .collect();
+        assert_eq!(order, ["/b", "/a"]);
+    }
+
+    #[test]
+    fn test_buffer_file_is_compacted_as_it_drains() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("buffer.jsonl");
+        let config = PauseConfig {
+            buffer_path: Some(path.clone()),
+            ..Default::default()
+        };
+        let control = PauseControl::new(&config).unwrap();
+        let chat = "https://chat.example.com";
+        control.pause(Scope::Target(chat.to_string())).unwrap();
+        for _ in 0..500 {
+            assert!(matches!(control.hold("/a", chat, || request("/a", chat)), Hold::Buffered));
+        }
+        control.resume(Scope::Target(chat.to_string())).unwrap();
+        for _ in 0..490 {
+            let next = control.next_ready().unwrap();
+            control.complete(next.id);
+        }
+        control.flush();
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        assert!(lines < 2 * COMPACT_MIN_LINES, "{} lines", lines);
+
+        // Requests sent before a restart are not buffered again
+        let control = PauseControl::new(&config).unwrap();
+        assert_eq!(control.status()["buffered"], 10);
+        assert_eq!(control.next_ready().unwrap().id, 491);
+    }
+}
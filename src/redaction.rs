@@ -0,0 +1,295 @@
+//! Redaction of sensitive values in logs and stored records.
+//!
+//! Rules come from `settings.redaction`:
+//!
+//! - `headers`: header names whose values are replaced, in addition to
+//!   `Authorization`, `Proxy-Authorization` and `Cookie`
+//! - `json_paths`: fields of JSON bodies whose values are replaced, as
+//!   `$.card.number`, `user.email` or `items[*].ssn`
+//! - `patterns`: regular expressions whose matches are replaced in any text,
+//!   such as card numbers or bearer tokens
+//!
+//! The rules are installed once at startup and apply to every log line and
+//! error response (through [`crate::secrets::redact`]), to debug output and to
+//! delivery records. In text, `json_paths` apply to any JSON embedded in it,
+//! such as a payload quoted in an error message.
+
+use crate::config::RedactionConfig;
+use crate::secrets::REDACTED;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Headers that always carry credentials.
+const CREDENTIAL_HEADERS: [&str; 3] = ["authorization", "proxy-authorization", "cookie"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Key(String),
+    /// `*` or `[*]`: every field of an object or element of an array
+    Any,
+}
+
+/// Compiled redaction rules.
+#[derive(Debug, Default)]
+pub struct Redactor {
+    headers: Vec<String>,
+    json_paths: Vec<Vec<Segment>>,
+    patterns: Vec<Regex>,
+}
+
+fn rules() -> &'static RwLock<Arc<Redactor>> {
+    static RULES: OnceLock<RwLock<Arc<Redactor>>> = OnceLock::new();
+    RULES.get_or_init(|| RwLock::new(Arc::new(Redactor::default())))
+}
+
+/// Make `redactor` the rules applied from now on.
+pub fn install(redactor: Redactor) {
+    *rules().write().unwrap_or_else(|e| e.into_inner()) = Arc::new(redactor);
+}
+
+/// The installed rules.
+pub fn current() -> Arc<Redactor> {
+    rules().read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    let trimmed = path.strip_prefix("$.").or_else(|| path.strip_prefix('$')).unwrap_or(path);
+    let mut segments = Vec::new();
+    for part in trimmed.split('.') {
+        let (key, indexes) = match part.find('[') {
+            Some(at) => part.split_at(at),
+            None => (part, ""),
+        };
+        match key {
+            "" if indexes.is_empty() => return Err(format!("invalid JSON path '{}'", path)),
+            "" => {}
+            "*" => segments.push(Segment::Any),
+            key => segments.push(Segment::Key(key.to_string())),
+        }
+        let mut rest = indexes;
+        while let Some(after) = rest.strip_prefix('[') {
+            let (index, remainder) = after
+                .split_once(']')
+                .ok_or_else(|| format!("invalid JSON path '{}'", path))?;
+            segments.push(match index {
+                "*" => Segment::Any,
+                index if index.parse::<usize>().is_ok() => Segment::Key(index.to_string()),
+                _ => return Err(format!("invalid JSON path '{}'", path)),
+            });
+            rest = remainder;
+        }
+        if !rest.is_empty() {
+            return Err(format!("invalid JSON path '{}'", path));
+        }
+    }
+    if segments.is_empty() {
+        return Err(format!("invalid JSON path '{}'", path));
+    }
+    Ok(segments)
+}
+
+fn redact_path(value: &mut Value, path: &[Segment]) {
+    let Some((segment, rest)) = path.split_first() else {
+        *value = Value::String(REDACTED.to_string());
+        return;
+    };
+    match (value, segment) {
+        (Value::Object(map), Segment::Key(key)) => {
+            if let Some(child) = map.get_mut(key) {
+                redact_path(child, rest);
+            }
+        }
+        (Value::Object(map), Segment::Any) => map.values_mut().for_each(|child| redact_path(child, rest)),
+        (Value::Array(items), Segment::Key(index)) => {
+            if let Some(child) = index.parse::<usize>().ok().and_then(|i| items.get_mut(i)) {
+                redact_path(child, rest);
+            }
+        }
+        (Value::Array(items), Segment::Any) => items.iter_mut().for_each(|child| redact_path(child, rest)),
+        _ => {}
+    }
+}
+
+impl Redactor {
+    pub fn compile(config: &RedactionConfig) -> Result<Self, String> {
+        let json_paths = config
+            .json_paths
+            .iter()
+            .map(|path| parse_path(path))
+            .collect::<Result<_, _>>()?;
+        let patterns = config
+            .patterns
+            .iter()
+            .map(|pattern| Regex::new(pattern).map_err(|e| format!("invalid pattern '{}': {}", pattern, e)))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            headers: config.headers.iter().map(|h| h.to_ascii_lowercase()).collect(),
+            json_paths,
+            patterns,
+        })
+    }
+
+    /// Replace every match of the patterns in `text`, and the configured
+    /// fields of JSON objects and arrays embedded in it.
+    pub fn text(&self, text: &str) -> String {
+        let mut output = text.to_string();
+        for pattern in &self.patterns {
+            if pattern.is_match(&output) {
+                output = pattern.replace_all(&output, REDACTED).into_owned();
+            }
+        }
+        if self.json_paths.is_empty() {
+            return output;
+        }
+        self.embedded_json(&output)
+    }
+
+    fn embedded_json(&self, text: &str) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(['{', '[']) {
+            output.push_str(&rest[..start]);
+            let candidate = &rest[start..];
+            let mut values = serde_json::Deserializer::from_str(candidate).into_iter::<Value>();
+            match values.next() {
+                Some(Ok(mut value)) => {
+                    let end = values.byte_offset();
+                    let original = value.clone();
+                    self.json(&mut value);
+                    match value == original {
+                        true => output.push_str(&candidate[..end]),
+                        false => output.push_str(&value.to_string()),
+                    }
+                    rest = &candidate[end..];
+                }
+                _ => {
+                    output.push_str(&candidate[..1]);
+                    rest = &candidate[1..];
+                }
+            }
+        }
+        output.push_str(rest);
+        output
+    }
+
+    /// Replace the configured fields of a JSON value, then pattern matches
+    /// and embedded JSON in its strings.
+    pub fn json(&self, value: &mut Value) {
+        for path in &self.json_paths {
+            redact_path(value, path);
+        }
+        if !self.patterns.is_empty() || !self.json_paths.is_empty() {
+            self.json_strings(value);
+        }
+    }
+
+    fn json_strings(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => *s = self.text(s),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.json_strings(item)),
+            Value::Object(map) => map.values_mut().for_each(|item| self.json_strings(item)),
+            _ => {}
+        }
+    }
+
+    /// Redact a body: JSON bodies field by field, anything else as text.
+    pub fn body(&self, body: &str) -> String {
+        self.body_checked(body).0
+    }
+
+    /// Redact a body, also telling whether any rule matched. JSON bodies are
+    /// re-serialized either way, so the output alone does not tell.
+    pub fn body_checked(&self, body: &str) -> (String, bool) {
+        match serde_json::from_str::<Value>(body) {
+            Ok(mut value) if value.is_object() || value.is_array() => {
+                let original = value.clone();
+                self.json(&mut value);
+                let changed = value != original;
+                (value.to_string(), changed)
+            }
+            _ => {
+                let redacted = self.text(body);
+                let changed = redacted != body;
+                (redacted, changed)
+            }
+        }
+    }
+
+    /// Header names and values, with redacted headers' values replaced.
+    pub fn headers<'a>(&self, headers: impl IntoIterator<Item = (&'a str, &'a str)>) -> BTreeMap<String, String> {
+        headers
+            .into_iter()
+            .map(|(name, value)| {
+                let lower = name.to_ascii_lowercase();
+                let value = if CREDENTIAL_HEADERS.contains(&lower.as_str()) || self.headers.contains(&lower) {
+                    REDACTED.to_string()
+                } else {
+                    self.text(value)
+                };
+                (lower, value)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor() -> Redactor {
+        Redactor::compile(&RedactionConfig {
+            headers: vec!["X-Api-Key".to_string()],
+            json_paths: vec!["$.card.number".to_string(), "items[*].ssn".to_string()],
+            patterns: vec![r"\b\d{4}(?:[ -]?\d{4}){3}\b".to_string(), r"(?i)bearer\s+[\w.~+/-]+=*".to_string()],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_json_paths_and_patterns() {
+        let redactor = redactor();
+        let body = r#"{"card":{"number":"4111","exp":"12/30"},"items":[{"ssn":"123-45-6789"},{"ssn":"987"}],"note":"paid with 4111 1111 1111 1111"}"#;
+        let redacted: Value = serde_json::from_str(&redactor.body(body)).unwrap();
+        assert_eq!(redacted["card"]["number"], REDACTED);
+        assert_eq!(redacted["card"]["exp"], "12/30");
+        assert_eq!(redacted["items"][1]["ssn"], REDACTED);
+        assert_eq!(redacted["note"], "paid with [REDACTED]");
+        assert!(redactor.body_checked(body).1);
+        assert!(!redactor.body_checked("{ \"card\": { \"exp\": \"12/30\" } }").1);
+
+        assert_eq!(redactor.text("failed with Bearer abc.def-123"), "failed with [REDACTED]");
+
+        // JSON quoted in log lines and error messages, even as a string in a
+        // JSON log line
+        let message = format!("Script execution failed: bad payload {} [at 1:2]", body);
+        let redacted = redactor.text(&message);
+        assert!(redacted.starts_with("Script execution failed: bad payload {"), "{}", redacted);
+        assert!(redacted.ends_with("} [at 1:2]"), "{}", redacted);
+        assert!(!redacted.contains("123-45-6789") && !redacted.contains("\"4111\""), "{}", redacted);
+        let line = serde_json::json!({ "level": "INFO", "fields": { "message": message } }).to_string();
+        let redacted = redactor.text(&line);
+        assert!(!redacted.contains("123-45-6789"), "{}", redacted);
+        let untouched = r#"{"level":"INFO","message":"sent [1, 2] to {target}"}"#;
+        assert_eq!(redactor.text(untouched), untouched);
+        assert!(Redactor::compile(&RedactionConfig {
+            json_paths: vec!["items[x]".to_string()],
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_headers() {
+        let headers = redactor().headers([
+            ("Authorization", "Basic dXNlcjpwYXNz"),
+            ("x-api-key", "k-123"),
+            ("x-github-event", "push"),
+        ]);
+        assert_eq!(headers["authorization"], REDACTED);
+        assert_eq!(headers["x-api-key"], REDACTED);
+        assert_eq!(headers["x-github-event"], "push");
+    }
+}
@@ -0,0 +1,59 @@
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+
+/// Header carrying the request ID on inbound requests and responses.
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest sender-supplied ID that is kept.
+const MAX_LEN: usize = 128;
+
+/// The ID of an inbound request: the sender's `X-Request-Id` when it is a
+/// sensible value, otherwise a new UUID.
+///
+/// The ID is written back to the inbound headers, so scripts, templates and
+/// the outbound request all see the ID in use.
+pub fn assign(headers: &mut HeaderMap) -> String {
+    let id = headers
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        headers.insert(REQUEST_ID_HEADER, value);
+    }
+    id
+}
+
+/// Parse the name of the header the request ID is forwarded to targets in.
+pub fn forward_header(name: &str) -> Result<reqwest::header::HeaderName, String> {
+    reqwest::header::HeaderName::from_bytes(name.as_bytes()).map_err(|e| format!("Invalid request ID header '{}': {}", name, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_honors_incoming_id() {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", "req-7f3a".parse().unwrap());
+        assert_eq!(assign(&mut headers), "req-7f3a");
+        assert_eq!(headers["x-request-id"], "req-7f3a");
+    }
+
+    #[test]
+    fn test_generates_id_when_missing_or_unusable() {
+        let mut headers = HeaderMap::new();
+        let id = assign(&mut headers);
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        assert_eq!(headers["x-request-id"], id.as_str());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", "has spaces".parse().unwrap());
+        assert_ne!(assign(&mut headers), "has spaces");
+        headers.insert("x-request-id", "a".repeat(MAX_LEN + 1).parse().unwrap());
+        assert_eq!(assign(&mut headers).len(), 36);
+    }
+}
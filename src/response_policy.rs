@@ -0,0 +1,275 @@
+use crate::config::ResponsePolicy;
+use axum::http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use handlebars::Handlebars;
+use serde_json::{Map, Value};
+
+/// Target response headers that are not passed through to the sender.
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "content-length",
+];
+
+/// A register's response policy, with its templates compiled.
+#[derive(Debug)]
+pub struct CompiledResponse {
+    policy: ResponsePolicy,
+    templates: Option<Handlebars<'static>>,
+}
+
+impl CompiledResponse {
+    /// Check a fixed response's status and headers and compile response
+    /// templates.
+    pub fn compile(policy: &ResponsePolicy) -> Result<Self, String> {
+        let templates = match policy {
+            ResponsePolicy::Fixed { status, headers, .. } => {
+                StatusCode::from_u16(*status).map_err(|_| format!("Invalid response status {}", status))?;
+                fixed_headers(headers)?;
+                None
+            }
+            ResponsePolicy::Template { status, headers, body } => {
+                let mut handlebars = Handlebars::new();
+                let mut register = |name: &str, template: &str| {
+                    handlebars
+                        .register_template_string(name, template)
+                        .map_err(|e| format!("Invalid response template {}: {}", name, e))
+                };
+                register("status", status)?;
+                register("body", body)?;
+                for (name, value) in headers {
+                    HeaderName::from_bytes(name.as_bytes())
+                        .map_err(|e| format!("Invalid response header name {}: {}", name, e))?;
+                    register(&format!("header:{}", name), value)?;
+                }
+                Some(handlebars)
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            policy: policy.clone(),
+            templates,
+        })
+    }
+
+    /// Build the response for the sender from the target's response.
+    ///
+    /// `data` is the template context of the request; response templates
+    /// additionally see the target response as `_target`.
+    pub async fn respond(&self, data: Map<String, Value>, target: reqwest::Response) -> Result<Response, String> {
+        let status = StatusCode::from_u16(target.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+
+        if self.policy == ResponsePolicy::Passthrough {
+            let mut headers = HeaderMap::new();
+            for (name, value) in target.headers() {
+                if HOP_BY_HOP_HEADERS.contains(&name.as_str()) {
+                    continue;
+                }
+                if let (Ok(name), Ok(value)) = (
+                    HeaderName::from_bytes(name.as_str().as_bytes()),
+                    HeaderValue::from_bytes(value.as_bytes()),
+                ) {
+                    headers.append(name, value);
+                }
+            }
+            let body = target
+                .bytes()
+                .await
+                .map_err(|e| format!("Failed to read response: {}", e))?;
+            return Ok((status, headers, body).into_response());
+        }
+
+        let target_headers: Map<String, Value> = target
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|v| (name.as_str().to_string(), Value::String(v.to_string())))
+            })
+            .collect();
+
+        // Get response body
+        let response_text = target
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read response: {}", e))?;
+
+        // Try to parse response as JSON, if it fails, return as string
+        let response_json = serde_json::from_str::<Value>(&response_text).unwrap_or(Value::String(response_text));
+
+        if let Some(templates) = &self.templates {
+            let mut data = data;
+            data.insert(
+                "_target".to_string(),
+                serde_json::json!({
+                    "status": status.as_u16(),
+                    "headers": target_headers,
+                    "body": response_json,
+                }),
+            );
+            return render_template(templates, &self.policy, &data);
+        }
+
+        if self.policy != ResponsePolicy::Wrap && !status.is_success() {
+            return Ok((
+                StatusCode::BAD_GATEWAY,
+                Json(serde_json::json!({
+                    "error": format!("Target responded with {}", status),
+                    "target_status": status.as_u16(),
+                    "target_response": response_json,
+                })),
+            )
+                .into_response());
+        }
+
+        match &self.policy {
+            ResponsePolicy::Fixed { status, headers, body } => {
+                let status = StatusCode::from_u16(*status).map_err(|e| e.to_string())?;
+                Ok((status, fixed_headers(headers)?, body.clone()).into_response())
+            }
+            _ => Ok(Json(serde_json::json!({
+                "status": "success",
+                "target_response": response_json
+            }))
+            .into_response()),
+        }
+    }
+}
+
+fn render_template(
+    templates: &Handlebars<'static>,
+    policy: &ResponsePolicy,
+    data: &Map<String, Value>,
+) -> Result<Response, String> {
+    let render = |name: &str| {
+        templates
+            .render(name, data)
+            .map_err(|e| format!("Response template {} failed: {}", name, e))
+    };
+
+    let status = render("status")?;
+    let status = status
+        .trim()
+        .parse::<u16>()
+        .ok()
+        .and_then(|code| StatusCode::from_u16(code).ok())
+        .ok_or_else(|| format!("Response template rendered invalid status '{}'", status.trim()))?;
+
+    let mut headers = HeaderMap::new();
+    if let ResponsePolicy::Template { headers: names, .. } = policy {
+        for name in names.keys() {
+            let value = render(&format!("header:{}", name))?;
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes()).map_err(|e| e.to_string())?,
+                HeaderValue::from_str(&value).map_err(|e| format!("Invalid response header value for {}: {}", name, e))?,
+            );
+        }
+    }
+
+    Ok((status, headers, render("body")?).into_response())
+}
+
+fn fixed_headers(headers: &std::collections::HashMap<String, String>) -> Result<HeaderMap, String> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| format!("Invalid response header name {}: {}", name, e))?;
+        let value = HeaderValue::from_str(value).map_err(|e| format!("Invalid response header value for {}: {}", name, e))?;
+        map.insert(name, value);
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+
+    async fn target_server() -> String {
+        let app = Router::new()
+            .route("/ok", post(|| async { ([("x-receiver", "ok")], Json(serde_json::json!({ "accepted": true }))) }))
+            .route("/fail", post(|| async { (StatusCode::NOT_FOUND, "no such channel") }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    async fn respond_to(policy: ResponsePolicy, url: String) -> (StatusCode, HeaderMap, Value) {
+        let target = reqwest::Client::new().post(url).send().await.unwrap();
+        let response = CompiledResponse::compile(&policy)
+            .unwrap()
+            .respond(Map::new(), target)
+            .await
+            .unwrap();
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&body).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()));
+        (parts.status, parts.headers, body)
+    }
+
+    #[tokio::test]
+    async fn test_wrap_and_map_errors() {
+        let base = target_server().await;
+
+        let (status, _, body) = respond_to(ResponsePolicy::Wrap, format!("{}/fail", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["target_response"], "no such channel");
+
+        let (status, _, body) = respond_to(ResponsePolicy::MapErrors, format!("{}/fail", base)).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(body["target_status"], 404);
+
+        let (status, _, body) = respond_to(ResponsePolicy::MapErrors, format!("{}/ok", base)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["target_response"]["accepted"], true);
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_and_fixed() {
+        let base = target_server().await;
+
+        let (status, _, body) = respond_to(ResponsePolicy::Passthrough, format!("{}/fail", base)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, "no such channel");
+
+        let (_, headers, _) = respond_to(ResponsePolicy::Passthrough, format!("{}/ok", base)).await;
+        assert_eq!(headers["x-receiver"], "ok");
+
+        let fixed = ResponsePolicy::Fixed {
+            status: 202,
+            headers: [("content-type".to_string(), "text/plain".to_string())].into(),
+            body: "queued".to_string(),
+        };
+        let (status, _, body) = respond_to(fixed.clone(), format!("{}/ok", base)).await;
+        assert_eq!((status, body), (StatusCode::ACCEPTED, Value::String("queued".to_string())));
+        let (status, _, _) = respond_to(fixed, format!("{}/fail", base)).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_template_response() {
+        let base = target_server().await;
+        let template = ResponsePolicy::Template {
+            status: "{{#if _target.body.accepted}}201{{else}}503{{/if}}".to_string(),
+            headers: [("content-type".to_string(), "text/xml".to_string())].into(),
+            body: "<Response><Message>{{ _target.status }}</Message></Response>".to_string(),
+        };
+
+        let (status, headers, body) = respond_to(template.clone(), format!("{}/ok", base)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(headers["content-type"], "text/xml");
+        assert_eq!(body, "<Response><Message>200</Message></Response>");
+
+        let (status, _, _) = respond_to(template, format!("{}/fail", base)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,472 @@
+//! The registers being served.
+//!
+//! Each register is compiled once into a [`Route`]: its template, target,
+//! response policy, inbound auth, script and wasm transform. The routes are
+//! swapped as a whole when the admin API changes a register, so a request
+//! always sees one consistent set. A change is compiled before it is
+//! applied, and with `settings.admin.persist` it is written back to the
+//! configuration file first.
+
+use crate::client_pool::ClientPool;
+use crate::config::{AppSettings, WebhookRegister};
+use crate::inbound_auth::InboundAuthenticator;
+use crate::response_policy::CompiledResponse;
+use crate::script::CompiledScript;
+use crate::sources::SourcePolicy;
+use crate::target::TargetTemplates;
+use crate::wasm::WasmTransform;
+use handlebars::{Context, Handlebars, Helper, HelperResult, Output, RenderContext};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+const TEMPLATE: &str = "template";
+
+fn escape_newlines_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    if let Some(param) = h.param(0) {
+        let raw = param.value().as_str().unwrap_or("");
+        let escaped = raw.replace('\n', "\\n");
+        out.write(&escaped)?;
+    }
+    Ok(())
+}
+
+/// A register and everything compiled from it.
+pub struct Route {
+    pub register: WebhookRegister,
+    /// The register as written, with `${...}` references unresolved
+    pub source: serde_yaml::Value,
+    handlebars: Handlebars<'static>,
+    pub authenticator: Option<InboundAuthenticator>,
+    pub script: Option<Arc<CompiledScript>>,
+    pub wasm: Option<Arc<WasmTransform>>,
+    pub target: TargetTemplates,
+    pub response: CompiledResponse,
+}
+
+impl Route {
+    pub fn compile(source: serde_yaml::Value, clients: &ClientPool) -> Result<Self, String> {
+        let mut resolved = source.clone();
+        crate::secrets::resolve(&mut resolved)?;
+        let register: WebhookRegister = serde_yaml::from_value(resolved).map_err(|e| e.to_string())?;
+        let endpoint = &register.endpoint;
+        if !endpoint.starts_with('/') {
+            return Err(format!("{}: endpoint must start with '/'", endpoint));
+        }
+
+        let mut handlebars = Handlebars::new();
+        handlebars.register_helper("escapeNewlines", Box::new(escape_newlines_helper));
+        handlebars
+            .register_template_string(TEMPLATE, &register.template)
+            .map_err(|e| format!("{}: template error: {}", endpoint, e))?;
+
+        let target = TargetTemplates::compile(&register.target).map_err(|e| format!("{}: {}", endpoint, e))?;
+        clients
+            .validate(&register.target)
+            .map_err(|e| format!("{}: {}", endpoint, e))?;
+        let response = CompiledResponse::compile(&register.response).map_err(|e| format!("{}: {}", endpoint, e))?;
+        if let Some(probe) = &register.probe {
+            crate::health::probe_request(&register, probe).map_err(|e| format!("{}: {}", endpoint, e))?;
+        }
+        let authenticator = register
+            .auth
+            .as_ref()
+            .map(InboundAuthenticator::compile)
+            .transpose()
+            .map_err(|e| format!("{}: {}", endpoint, e))?;
+        let script = register
+            .script
+            .as_ref()
+            .map(|script| CompiledScript::compile(script).map(Arc::new))
+            .transpose()
+            .map_err(|e| format!("{}: {}", endpoint, e))?;
+        let wasm = register
+            .wasm
+            .as_ref()
+            .map(|wasm| WasmTransform::load(wasm).map(Arc::new))
+            .transpose()
+            .map_err(|e| format!("{}: {}", endpoint, e))?;
+
+        Ok(Self {
+            register,
+            source,
+            handlebars,
+            authenticator,
+            script,
+            wasm,
+            target,
+            response,
+        })
+    }
+
+    pub fn render_template(&self, data: &Map<String, Value>) -> Result<String, String> {
+        self.handlebars.render(TEMPLATE, data).map_err(|e| e.to_string())
+    }
+
+    /// The register as written, as returned by the admin API.
+    pub fn view(&self) -> Value {
+        let mut view = serde_json::to_value(&self.source).unwrap_or_default();
+        if let Value::Object(map) = &mut view {
+            map.insert("enabled".to_string(), Value::Bool(self.register.enabled));
+        }
+        view
+    }
+}
+
+/// The routes serving requests at one point in time.
+pub struct Routes {
+    order: Vec<String>,
+    routes: HashMap<String, Arc<Route>>,
+    pub sources: SourcePolicy,
+}
+
+impl Routes {
+    pub fn get(&self, endpoint: &str) -> Option<&Arc<Route>> {
+        self.routes.get(endpoint)
+    }
+
+    /// The routes in configuration order.
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Route>> {
+        self.order.iter().map(|endpoint| &self.routes[endpoint])
+    }
+}
+
+/// A change to the registers.
+pub enum Change {
+    Create(serde_yaml::Value),
+    Update(String, serde_yaml::Value),
+    Delete(String),
+    SetEnabled(String, bool),
+}
+
+#[derive(Debug)]
+pub enum ChangeError {
+    NotFound(String),
+    Conflict(String),
+    Invalid(String),
+    Persist(String),
+}
+
+impl std::fmt::Display for ChangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound(endpoint) => write!(f, "Register {} not found", endpoint),
+            Self::Conflict(endpoint) => write!(f, "Register {} already exists", endpoint),
+            Self::Invalid(e) => write!(f, "Invalid register: {}", e),
+            Self::Persist(e) => write!(f, "Failed to persist configuration: {}", e),
+        }
+    }
+}
+
+/// The current routes, and the changes made to them at runtime.
+pub struct RouteTable {
+    current: RwLock<Arc<Routes>>,
+    /// Held while a change is compiled and persisted, so changes apply in turn
+    changing: Mutex<()>,
+    /// Signalled whenever the routes are replaced
+    changed: watch::Sender<()>,
+    settings: AppSettings,
+    clients: Arc<ClientPool>,
+    persist_path: Option<PathBuf>,
+}
+
+impl RouteTable {
+    /// Compile the registers as written in the configuration file. Changes
+    /// are written back to `persist_path` if given.
+    pub fn new(
+        sources: Vec<serde_yaml::Value>,
+        settings: &AppSettings,
+        clients: Arc<ClientPool>,
+        persist_path: Option<PathBuf>,
+    ) -> Result<Self, String> {
+        let mut order = Vec::new();
+        let mut routes = HashMap::new();
+        for source in sources {
+            let route = Route::compile(source, &clients)?;
+            let endpoint = route.register.endpoint.clone();
+            if routes.insert(endpoint.clone(), Arc::new(route)).is_some() {
+                return Err(format!("{}: registered more than once", endpoint));
+            }
+            order.push(endpoint);
+        }
+        let registers: Vec<_> = order.iter().map(|e| routes[e].register.clone()).collect();
+        let sources = SourcePolicy::new(settings, &registers)?;
+
+        Ok(Self {
+            current: RwLock::new(Arc::new(Routes { order, routes, sources })),
+            changing: Mutex::new(()),
+            changed: watch::channel(()).0,
+            settings: settings.clone(),
+            clients,
+            persist_path,
+        })
+    }
+
+    pub fn current(&self) -> Arc<Routes> {
+        self.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Notified each time a change is applied.
+    pub fn changes(&self) -> watch::Receiver<()> {
+        self.changed.subscribe()
+    }
+
+    /// Compile and apply `change`, returning the changed route (none for a
+    /// deletion). With `dry_run` the change is only compiled. Compiles and
+    /// writes files, so call it from a blocking context.
+    pub fn apply(&self, change: Change, dry_run: bool) -> Result<Option<Arc<Route>>, ChangeError> {
+        let _changing = self.changing.lock().unwrap_or_else(|e| e.into_inner());
+        let current = self.current();
+        let mut order = current.order.clone();
+        let mut routes = current.routes.clone();
+        let compile = |source| Route::compile(source, &self.clients).map(Arc::new).map_err(ChangeError::Invalid);
+
+        let changed = match change {
+            Change::Create(source) => {
+                check_submitted(&source).map_err(ChangeError::Invalid)?;
+                let route = compile(source)?;
+                let endpoint = route.register.endpoint.clone();
+                if routes.contains_key(&endpoint) {
+                    return Err(ChangeError::Conflict(endpoint));
+                }
+                order.push(endpoint.clone());
+                routes.insert(endpoint, route.clone());
+                Some(route)
+            }
+            Change::Update(endpoint, source) => {
+                let position = order
+                    .iter()
+                    .position(|e| *e == endpoint)
+                    .ok_or_else(|| ChangeError::NotFound(endpoint.clone()))?;
+                check_submitted(&source).map_err(ChangeError::Invalid)?;
+                let route = compile(source)?;
+                let renamed = &route.register.endpoint;
+                if *renamed != endpoint && routes.contains_key(renamed) {
+                    return Err(ChangeError::Conflict(renamed.clone()));
+                }
+                routes.remove(&endpoint);
+                order[position] = renamed.clone();
+                routes.insert(renamed.clone(), route.clone());
+                Some(route)
+            }
+            Change::Delete(endpoint) => {
+                routes.remove(&endpoint).ok_or_else(|| ChangeError::NotFound(endpoint.clone()))?;
+                order.retain(|e| *e != endpoint);
+                None
+            }
+            Change::SetEnabled(endpoint, enabled) => {
+                let route = routes.get(&endpoint).ok_or_else(|| ChangeError::NotFound(endpoint.clone()))?;
+                let mut source = route.source.clone();
+                if let serde_yaml::Value::Mapping(map) = &mut source {
+                    // Enabled is the default, so only disabled registers say so
+                    match enabled {
+                        true => map.remove("enabled"),
+                        false => map.insert("enabled".into(), false.into()),
+                    };
+                }
+                let route = compile(source)?;
+                routes.insert(endpoint, route.clone());
+                Some(route)
+            }
+        };
+
+        let registers: Vec<_> = order.iter().map(|e| routes[e].register.clone()).collect();
+        let sources = SourcePolicy::new(&self.settings, &registers).map_err(ChangeError::Invalid)?;
+        if dry_run {
+            return Ok(changed);
+        }
+
+        if let Some(path) = &self.persist_path {
+            let written: Vec<_> = order.iter().map(|e| routes[e].source.clone()).collect();
+            persist(path, written).map_err(ChangeError::Persist)?;
+        }
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(Routes { order, routes, sources });
+        self.changed.send_replace(());
+        Ok(changed)
+    }
+
+    /// Reload source ranges files whenever the process receives `SIGHUP`.
+    #[cfg(unix)]
+    pub fn spawn_reload_on_hangup(self: Arc<Self>) {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        tokio::spawn(async move {
+            let mut hangup = match signal(SignalKind::hangup()) {
+                Ok(hangup) => hangup,
+                Err(e) => {
+                    warn!(error = %e, "Failed to install SIGHUP handler");
+                    return;
+                }
+            };
+            while hangup.recv().await.is_some() {
+                self.current().sources.reload();
+            }
+        });
+    }
+
+    #[cfg(not(unix))]
+    pub fn spawn_reload_on_hangup(self: Arc<Self>) {}
+}
+
+/// Check a register submitted through the admin API. Secret references and
+/// ranges files are only read for registers in the configuration file, so an
+/// admin token cannot be used to send secrets or file contents elsewhere.
+fn check_submitted(source: &serde_yaml::Value) -> Result<(), String> {
+    let references = crate::secrets::references(source);
+    if !references.is_empty() {
+        return Err(format!(
+            "secret references are only resolved in the configuration file: {}",
+            references.join(", ")
+        ));
+    }
+    let files = source["allowed_sources"]
+        .as_sequence()
+        .into_iter()
+        .flatten()
+        .any(|entry| entry.as_str().is_some_and(|entry| entry.starts_with("file:")));
+    if files {
+        return Err("allowed_sources: file: ranges are only read from the configuration file".to_string());
+    }
+    Ok(())
+}
+
+/// Replace the registers in the configuration file, leaving the rest as it
+/// is. Only the top-level `registers` block is rewritten, so comments
+/// elsewhere are kept; if the file cannot be spliced that way it is written
+/// out whole, without comments. The file is rewritten through a temporary
+/// file, so readers see either the old or the new version.
+fn persist(path: &Path, registers: Vec<serde_yaml::Value>) -> Result<(), String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut config: serde_yaml::Value = serde_yaml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let serde_yaml::Value::Mapping(map) = &mut config else {
+        return Err(format!("{}: not a mapping", path.display()));
+    };
+    let mut block = serde_yaml::Mapping::new();
+    block.insert("registers".into(), serde_yaml::Value::Sequence(registers.clone()));
+    let block = serde_yaml::to_string(&block).map_err(|e| e.to_string())?;
+    map.insert("registers".into(), serde_yaml::Value::Sequence(registers));
+
+    let yaml = match splice_registers(&content, &block) {
+        Some(yaml) if serde_yaml::from_str::<serde_yaml::Value>(&yaml).is_ok_and(|spliced| spliced == config) => yaml,
+        _ => {
+            warn!(path = %path.display(), "Rewriting the whole configuration file; its comments are not kept");
+            serde_yaml::to_string(&config).map_err(|e| e.to_string())?
+        }
+    };
+
+    let file_name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    let temporary = path.with_file_name(format!(".{}.tmp", file_name));
+    let written = std::fs::File::create(&temporary).and_then(|mut file| {
+        file.write_all(yaml.as_bytes())?;
+        file.set_permissions(std::fs::metadata(path)?.permissions())?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|()| std::fs::rename(&temporary, path)) {
+        let _ = std::fs::remove_file(&temporary);
+        return Err(format!("{}: {}", path.display(), e));
+    }
+    info!(path = %path.display(), "Persisted register changes");
+    Ok(())
+}
+
+/// `content` with its top-level `registers` block replaced by `block`, or
+/// appended if it has none. The block runs to the next top-level key, less
+/// the blank and comment lines leading up to it.
+fn splice_registers(content: &str, block: &str) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let top_level = |line: &str| !line.is_empty() && !line.starts_with([' ', '\t', '#', '-']);
+    if lines.iter().any(|line| line.starts_with("---") || line.starts_with("...")) {
+        return None;
+    }
+
+    let Some(start) = lines.iter().position(|line| line.starts_with("registers:")) else {
+        let mut spliced = content.to_string();
+        if !spliced.is_empty() && !spliced.ends_with('\n') {
+            spliced.push('\n');
+        }
+        return Some(spliced + block);
+    };
+    let mut end = lines[start + 1..]
+        .iter()
+        .position(|line| top_level(line))
+        .map_or(lines.len(), |i| start + 1 + i);
+    while end > start + 1 && matches!(lines[end - 1].trim_start().chars().next(), None | Some('#')) {
+        end -= 1;
+    }
+
+    let mut spliced: String = lines[..start].iter().map(|line| format!("{}\n", line)).collect();
+    spliced.push_str(block);
+    for line in &lines[end..] {
+        spliced.push_str(line);
+        spliced.push('\n');
+    }
+    Some(spliced)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EgressConfig;
+    use crate::egress::EgressPolicy;
+    use std::time::Duration;
+
+    fn register(endpoint: &str, template: &str) -> serde_yaml::Value {
+        serde_yaml::from_str(&format!(
+            "endpoint: {}\nmethod: POST\ntarget: {{ url: 'https://chat.example.com/hooks', method: POST }}\ntemplate: '{}'",
+            endpoint, template
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_changes_are_validated_and_persisted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.yml");
+        std::fs::write(
+            &path,
+            "# Webhook routes\nregisters: []\n\n# Shared settings\nsettings:\n  retry_attempts: 5 # per request\n",
+        )
+        .unwrap();
+        let clients = Arc::new(ClientPool::new(Duration::from_secs(5), EgressPolicy::compile(&EgressConfig::default()).unwrap()).unwrap());
+        let table = RouteTable::new(vec![register("/a", "{}")], &AppSettings::default(), clients, Some(path.clone())).unwrap();
+
+        assert!(matches!(table.apply(Change::Create(register("/b", "{{#if}}")), false), Err(ChangeError::Invalid(_))));
+        assert!(matches!(table.apply(Change::Create(register("/a", "{}")), false), Err(ChangeError::Conflict(_))));
+        // Submitted registers cannot read secrets or files
+        for field in ["headers: { X-Key: '${HOME}' }", "allowed_sources: ['file:/etc/passwd']"] {
+            let mut source = register("/c", "{}");
+            let extra: serde_yaml::Mapping = serde_yaml::from_str(field).unwrap();
+            source.as_mapping_mut().unwrap().extend(extra);
+            assert!(matches!(table.apply(Change::Create(source), true), Err(ChangeError::Invalid(_))), "{}", field);
+        }
+        table.apply(Change::Create(register("/b", r#"{"b": 1}"#)), true).unwrap();
+        assert!(table.current().get("/b").is_none());
+
+        table.apply(Change::Create(register("/b", r#"{"b": 1}"#)), false).unwrap();
+        table.apply(Change::SetEnabled("/a".to_string(), false), false).unwrap();
+        let routes = table.current();
+        assert!(!routes.get("/a").unwrap().register.enabled);
+        assert_eq!(routes.get("/b").unwrap().render_template(&Map::new()).unwrap(), r#"{"b": 1}"#);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# Webhook routes\n"));
+        assert!(content.contains("\n\n# Shared settings\nsettings:\n  retry_attempts: 5 # per request\n"));
+        let written: serde_yaml::Value = serde_yaml::from_str(&content).unwrap();
+        assert_eq!(written["settings"]["retry_attempts"], 5);
+        assert_eq!(written["registers"][0]["enabled"], false);
+        assert_eq!(written["registers"][1]["endpoint"], "/b");
+
+        table.apply(Change::Delete("/a".to_string()), false).unwrap();
+        assert_eq!(table.current().iter().count(), 1);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::config::ScriptConfig;
+use rhai::{Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A per-register Rhai script, compiled once at startup.
+///
+/// The script runs with these variables in scope:
+/// - `request`: read-only map with `endpoint`, `method`, inbound `headers`
+///   and the raw `body`
+/// - `payload`: the parsed inbound payload, may be modified or replaced
+/// - `headers`: map of extra outbound headers to set on the target request
+/// - `target_url`: the target URL, may be replaced to route elsewhere
+/// - `drop`: set to `true` to accept the event without forwarding it
+#[derive(Debug)]
+pub struct CompiledScript {
+    ast: AST,
+    max_operations: u64,
+    timeout: Duration,
+}
+
+/// What a script decided for a single event.
+#[derive(Debug, Clone)]
+pub struct ScriptOutcome {
+    pub payload: Value,
+    pub headers: HashMap<String, String>,
+    pub target_url: String,
+    pub drop: bool,
+}
+
+impl CompiledScript {
+    pub fn compile(config: &ScriptConfig) -> Result<Self, String> {
+        let source = match (&config.source, &config.path) {
+            (Some(source), None) => source.clone(),
+            (None, Some(path)) => std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read script {}: {}", path.display(), e))?,
+            (Some(_), Some(_)) => return Err("script must set only one of 'source' or 'path'".to_string()),
+            (None, None) => return Err("script must set either 'source' or 'path'".to_string()),
+        };
+
+        let engine = new_engine(config.max_operations);
+        let ast = engine
+            .compile(&source)
+            .map_err(|e| format!("script compile error: {}", e))?;
+
+        Ok(Self {
+            ast,
+            max_operations: config.max_operations,
+            timeout: Duration::from_millis(config.timeout_ms),
+        })
+    }
+
+    pub fn run(&self, request: &Value, payload: &Value, target_url: &str) -> Result<ScriptOutcome, String> {
+        let mut engine = new_engine(self.max_operations);
+        let started = Instant::now();
+        let timeout = self.timeout;
+        engine.on_progress(move |_| {
+            if started.elapsed() > timeout {
+                Some(Dynamic::from("script timed out"))
+            } else {
+                None
+            }
+        });
+
+        let mut scope = Scope::new();
+        scope.push_constant_dynamic("request", to_dynamic(request)?);
+        scope.push_dynamic("payload", to_dynamic(payload)?);
+        scope.push("headers", rhai::Map::new());
+        scope.push("target_url", target_url.to_string());
+        scope.push("drop", false);
+
+        engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| match *e {
+                EvalAltResult::ErrorTerminated(reason, _) => format!("script aborted: {}", reason),
+                EvalAltResult::ErrorTooManyOperations(_) => {
+                    format!("script exceeded {} operations", self.max_operations)
+                }
+                other => format!("script error: {}", other),
+            })?;
+
+        let payload = scope
+            .get_value::<Dynamic>("payload")
+            .map(|d| rhai::serde::from_dynamic::<Value>(&d))
+            .transpose()
+            .map_err(|e| format!("script produced an invalid payload: {}", e))?
+            .unwrap_or(Value::Null);
+
+        let headers = scope
+            .get_value::<Dynamic>("headers")
+            .and_then(|d| d.try_cast::<rhai::Map>())
+            .ok_or_else(|| "script 'headers' must be a map".to_string())?
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        let target_url = scope
+            .get_value::<Dynamic>("target_url")
+            .and_then(|d| d.into_immutable_string().ok())
+            .ok_or_else(|| "script 'target_url' must be a string".to_string())?
+            .to_string();
+
+        let drop = scope
+            .get_value::<Dynamic>("drop")
+            .and_then(|d| d.as_bool().ok())
+            .ok_or_else(|| "script 'drop' must be a boolean".to_string())?;
+
+        Ok(ScriptOutcome {
+            payload,
+            headers,
+            target_url,
+            drop,
+        })
+    }
+}
+
+fn new_engine(max_operations: u64) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(max_operations);
+    engine.set_max_call_levels(32);
+    engine.set_max_string_size(1024 * 1024);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(10_000);
+    engine.disable_symbol("eval");
+    engine.on_print(|s| tracing::info!(target: "hermes_rs::script", "{}", s));
+    engine.on_debug(|s, _, pos| tracing::debug!(target: "hermes_rs::script", position = %pos, "{}", s));
+    engine
+}
+
+fn to_dynamic(value: &Value) -> Result<Dynamic, String> {
+    rhai::serde::to_dynamic(value).map_err(|e| format!("failed to pass value to script: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn script(source: &str) -> CompiledScript {
+        CompiledScript::compile(&ScriptConfig {
+            source: Some(source.to_string()),
+            path: None,
+            max_operations: 10_000,
+            timeout_ms: 1_000,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_script_mutates_payload_and_headers() {
+        let compiled = script(
+            r#"
+            payload.severity = if payload.count > 10 { "critical" } else { "warning" };
+            headers["X-Severity"] = payload.severity;
+            target_url = target_url + "/" + payload.team;
+            "#,
+        );
+        let outcome = compiled
+            .run(&json!({}), &json!({"count": 12, "team": "infra"}), "http://example.com")
+            .unwrap();
+
+        assert_eq!(outcome.payload["severity"], "critical");
+        assert_eq!(outcome.headers.get("X-Severity").unwrap(), "critical");
+        assert_eq!(outcome.target_url, "http://example.com/infra");
+        assert!(!outcome.drop);
+    }
+
+    #[test]
+    fn test_script_can_drop_event() {
+        let compiled = script(r#"if request.headers["x-test"] == "1" { drop = true; }"#);
+        let outcome = compiled
+            .run(&json!({"headers": {"x-test": "1"}}), &json!({}), "http://example.com")
+            .unwrap();
+        assert!(outcome.drop);
+    }
+
+    #[test]
+    fn test_script_operation_limit() {
+        let compiled = script("loop { }");
+        let err = compiled.run(&json!({}), &json!({}), "http://example.com").unwrap_err();
+        assert!(err.contains("operations"), "{}", err);
+    }
+
+    #[test]
+    fn test_script_compile_error() {
+        let err = CompiledScript::compile(&ScriptConfig {
+            source: Some("let x = ;".to_string()),
+            path: None,
+            max_operations: 10_000,
+            timeout_ms: 1_000,
+        })
+        .unwrap_err();
+        assert!(err.contains("compile error"));
+    }
+}
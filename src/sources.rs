@@ -0,0 +1,313 @@
+//! Source address restrictions.
+//!
+//! `allowed_sources` lists, globally in `settings` and per register, accept
+//! CIDRs, single addresses and `file:<path>` references to a local file of
+//! ranges (one per line, `#` starts a comment). Ranges files are re-read when
+//! the process receives `SIGHUP`.
+//!
+//! A register's own list replaces the global list for its endpoint, so one
+//! register can accept only GitHub's hook ranges and another only cluster
+//! CIDRs. Registers without a list, and unknown endpoints, use the global
+//! list.
+//!
+//! Behind a reverse proxy the client address is taken from `Forwarded` or
+//! `X-Forwarded-For`, but only for hops added by `settings.trusted_proxies`.
+
+use crate::config::{AppSettings, WebhookRegister};
+use axum::http::HeaderMap;
+use ipnet::IpNet;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::RwLock;
+use tracing::{info, warn};
+
+/// A list of allowed source ranges.
+#[derive(Debug)]
+pub struct AllowList {
+    nets: Vec<IpNet>,
+    files: Vec<PathBuf>,
+    file_nets: RwLock<Vec<IpNet>>,
+}
+
+impl AllowList {
+    pub fn compile(entries: &[String]) -> Result<Self, String> {
+        let mut nets = Vec::new();
+        let mut files = Vec::new();
+        for entry in entries {
+            match entry.strip_prefix("file:") {
+                Some(path) => files.push(PathBuf::from(path)),
+                None => nets.push(parse_net(entry)?),
+            }
+        }
+
+        let list = Self {
+            nets,
+            files,
+            file_nets: RwLock::new(Vec::new()),
+        };
+        list.reload()?;
+        Ok(list)
+    }
+
+    /// Re-read the ranges files. On error the previous ranges are kept.
+    pub fn reload(&self) -> Result<(), String> {
+        let mut file_nets = Vec::new();
+        for path in &self.files {
+            let contents = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            for (number, line) in contents.lines().enumerate() {
+                let line = line.split('#').next().unwrap_or_default().trim();
+                if !line.is_empty() {
+                    file_nets.push(
+                        // Without the line itself, so errors never echo a file's contents
+                        parse_net(line).map_err(|_| {
+                            format!("{}:{}: invalid CIDR or IP address", path.display(), number + 1)
+                        })?,
+                    );
+                }
+            }
+        }
+        *self.file_nets.write().unwrap_or_else(|e| e.into_inner()) = file_nets;
+        Ok(())
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        self.nets.iter().any(|net| net.contains(&ip))
+            || self
+                .file_nets
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .iter()
+                .any(|net| net.contains(&ip))
+    }
+}
+
+pub fn parse_net(entry: &str) -> Result<IpNet, String> {
+    let entry = entry.trim();
+    entry
+        .parse::<IpNet>()
+        .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("Invalid CIDR or IP address '{}'", entry))
+}
+
+/// Treat IPv4-mapped IPv6 addresses (from dual-stack listeners) as IPv4.
+pub fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        v4 => v4,
+    }
+}
+
+/// Global and per-register source restrictions.
+#[derive(Debug)]
+pub struct SourcePolicy {
+    trusted_proxies: Vec<IpNet>,
+    global: Option<AllowList>,
+    registers: HashMap<String, AllowList>,
+}
+
+impl SourcePolicy {
+    pub fn new(settings: &AppSettings, registers: &[WebhookRegister]) -> Result<Self, String> {
+        let trusted_proxies = settings
+            .trusted_proxies
+            .iter()
+            .map(|entry| parse_net(entry))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("settings.trusted_proxies: {}", e))?;
+
+        let global = match settings.allowed_sources.is_empty() {
+            true => None,
+            false => Some(
+                AllowList::compile(&settings.allowed_sources)
+                    .map_err(|e| format!("settings.allowed_sources: {}", e))?,
+            ),
+        };
+
+        let mut lists = HashMap::new();
+        for register in registers.iter().filter(|r| !r.allowed_sources.is_empty()) {
+            let list = AllowList::compile(&register.allowed_sources)
+                .map_err(|e| format!("{}: allowed_sources: {}", register.endpoint, e))?;
+            lists.insert(register.endpoint.clone(), list);
+        }
+
+        Ok(Self {
+            trusted_proxies,
+            global,
+            registers: lists,
+        })
+    }
+
+    /// The client address for a connection from `peer`.
+    ///
+    /// Forwarding headers are walked from the nearest hop outwards and each
+    /// hop is believed only while the address that reported it is a trusted
+    /// proxy. `Forwarded` takes precedence over `X-Forwarded-For`.
+    pub fn client_ip(&self, peer: SocketAddr, headers: &HeaderMap) -> IpAddr {
+        let mut ip = canonical(peer.ip());
+        if !self.is_trusted_proxy(ip) {
+            return ip;
+        }
+
+        let hops: Vec<Option<IpAddr>> = if headers.contains_key("forwarded") {
+            header_values(headers, "forwarded")
+                .flat_map(|element| {
+                    element
+                        .split(';')
+                        .filter_map(|pair| pair.trim().split_once('='))
+                        .filter(|(key, _)| key.eq_ignore_ascii_case("for"))
+                        .map(|(_, value)| parse_forwarded_for(value))
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        } else {
+            header_values(headers, "x-forwarded-for")
+                .map(parse_forwarded_for)
+                .collect()
+        };
+
+        for hop in hops.into_iter().rev() {
+            if !self.is_trusted_proxy(ip) {
+                break;
+            }
+            match hop {
+                Some(hop) => ip = canonical(hop),
+                // Obfuscated or malformed hops end the chain
+                None => break,
+            }
+        }
+        ip
+    }
+
+    fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(&ip))
+    }
+
+    /// Whether `ip` may send to `endpoint`, per the register's own list or
+    /// else the global one.
+    pub fn allows(&self, endpoint: &str, ip: IpAddr) -> bool {
+        self.registers
+            .get(endpoint)
+            .or(self.global.as_ref())
+            .is_none_or(|list| list.contains(ip))
+    }
+
+    /// Re-read every ranges file, keeping the previous ranges of lists that
+    /// fail to reload.
+    pub fn reload(&self) {
+        for (name, list) in self.global.iter().map(|l| ("settings", l)).chain(
+            self.registers
+                .iter()
+                .map(|(endpoint, list)| (endpoint.as_str(), list)),
+        ) {
+            if list.files.is_empty() {
+                continue;
+            }
+            match list.reload() {
+                Ok(()) => info!(allowed_sources = name, "Reloaded source ranges"),
+                Err(e) => warn!(allowed_sources = name, error = %e, "Failed to reload source ranges"),
+            }
+        }
+    }
+}
+
+fn header_values<'a>(headers: &'a HeaderMap, name: &str) -> impl Iterator<Item = &'a str> {
+    headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+}
+
+/// Parse a forwarded node such as `192.0.2.1`, `192.0.2.1:8080`,
+/// `"[2001:db8::1]:4711"` or `2001:db8::1`.
+fn parse_forwarded_for(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    value
+        .strip_prefix('[')
+        .and_then(|v| v.split_once(']'))
+        .and_then(|(ip, _)| ip.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use std::io::Write;
+
+    fn policy(settings: serde_json::Value) -> SourcePolicy {
+        SourcePolicy::new(&serde_json::from_value(settings).unwrap(), &[]).unwrap()
+    }
+
+    #[test]
+    fn test_client_ip_only_trusts_configured_proxies() {
+        let policy = policy(serde_json::json!({ "trusted_proxies": ["10.0.0.0/8"] }));
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", HeaderValue::from_static("198.51.100.7, 203.0.113.9, 10.1.2.3"));
+
+        // The untrusted hop closest to us is the client, not the spoofable first entry
+        assert_eq!(policy.client_ip("10.0.0.1:5000".parse().unwrap(), &headers), "203.0.113.9".parse::<IpAddr>().unwrap());
+        // Headers from an untrusted peer are ignored
+        assert_eq!(policy.client_ip("192.0.2.1:5000".parse().unwrap(), &headers), "192.0.2.1".parse::<IpAddr>().unwrap());
+
+        headers.insert("forwarded", HeaderValue::from_static("for=192.0.2.60;proto=https, for=\"[2001:db8::1]:4711\""));
+        assert_eq!(policy.client_ip("10.0.0.1:5000".parse().unwrap(), &headers), "2001:db8::1".parse::<IpAddr>().unwrap());
+    }
+
+    #[test]
+    fn test_allow_list_with_ranges_file_reload() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "# GitHub hooks\n192.30.252.0/22").unwrap();
+        let list = AllowList::compile(&["10.0.0.0/8".to_string(), format!("file:{}", file.path().display())]).unwrap();
+
+        assert!(list.contains("10.20.30.40".parse().unwrap()));
+        assert!(list.contains("192.30.253.1".parse().unwrap()));
+        assert!(list.contains("::ffff:192.30.253.1".parse().unwrap()));
+        assert!(!list.contains("140.82.112.1".parse().unwrap()));
+
+        writeln!(file, "140.82.112.0/20").unwrap();
+        list.reload().unwrap();
+        assert!(list.contains("140.82.112.1".parse().unwrap()));
+
+        // A broken file keeps the previous ranges
+        writeln!(file, "not-a-range").unwrap();
+        assert!(list.reload().unwrap_err().contains(":4:"));
+        assert!(list.contains("140.82.112.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_policy_global_and_register_lists() {
+        let register = |endpoint: &str, allowed_sources: &[&str]| -> WebhookRegister {
+            serde_json::from_value(serde_json::json!({
+                "endpoint": endpoint,
+                "method": "POST",
+                "target": { "url": "http://receiver/hook", "method": "POST" },
+                "allowed_sources": allowed_sources,
+            }))
+            .unwrap()
+        };
+        let registers = [
+            register("/webhook/alertmanager", &["10.42.0.0/16"]),
+            register("/webhook/github", &["192.30.252.0/22"]),
+        ];
+        let settings = serde_json::from_value(serde_json::json!({ "allowed_sources": ["10.0.0.0/8"] })).unwrap();
+        let policy = SourcePolicy::new(&settings, &registers).unwrap();
+
+        // Registers without a list, and unknown endpoints, use the global list
+        assert!(policy.allows("/webhook/other", "10.1.0.1".parse().unwrap()));
+        assert!(!policy.allows("/webhook/other", "192.0.2.1".parse().unwrap()));
+        // A register's list replaces the global one, widening as well as narrowing it
+        assert!(policy.allows("/webhook/github", "192.30.252.1".parse().unwrap()));
+        assert!(!policy.allows("/webhook/github", "10.1.0.1".parse().unwrap()));
+        assert!(policy.allows("/webhook/alertmanager", "10.42.1.1".parse().unwrap()));
+        assert!(!policy.allows("/webhook/alertmanager", "10.1.0.1".parse().unwrap()));
+    }
+}
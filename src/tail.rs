@@ -0,0 +1,161 @@
+//! Live tail of processed webhooks.
+//!
+//! Every webhook that reaches a register is published as a [`TailEvent`] to
+//! `GET /admin/tail` subscribers, which receive them as Server-Sent Events,
+//! optionally only for one endpoint. `hermes-admin tail` follows the stream.
+
+use crate::delivery_log::{DeliveryRecord, Outcome};
+use axum::response::sse::Event;
+use chrono::{DateTime, Utc};
+use futures_util::Stream;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// Characters of the rendered body included in an event.
+const PREVIEW_CHARS: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TailEvent {
+    pub request_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub endpoint: String,
+    pub outcome: Outcome,
+    /// `METHOD url` of the target request, when one was made
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+    pub attempts: u32,
+    /// The start of the redacted rendered body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TailEvent {
+    pub fn from_record(record: &DeliveryRecord) -> Self {
+        let preview = record.rendered_body.as_deref().map(|body| {
+            let body = crate::redaction::current().body(body);
+            match body.char_indices().nth(PREVIEW_CHARS) {
+                Some((end, _)) => format!("{}…", &body[..end]),
+                None => body,
+            }
+        });
+        Self {
+            request_id: record.request_id.clone(),
+            timestamp: record.timestamp,
+            endpoint: record.endpoint.clone(),
+            outcome: record.outcome,
+            route: record.target.as_ref().map(|t| format!("{} {}", t.method, t.url)),
+            status: record.status,
+            latency_ms: record.latency_ms,
+            attempts: record.attempts,
+            preview,
+            error: record.error.as_deref().map(crate::secrets::redact),
+        }
+    }
+}
+
+/// Publishes tail events to the connected subscribers.
+#[derive(Clone)]
+pub struct Tail(broadcast::Sender<Arc<TailEvent>>);
+
+impl Default for Tail {
+    fn default() -> Self {
+        Self(broadcast::channel(256).0)
+    }
+}
+
+impl Tail {
+    /// Publish a finished record; nothing is built when no one is watching.
+    pub fn publish(&self, record: &DeliveryRecord) {
+        if self.0.receiver_count() > 0 {
+            let _ = self.0.send(Arc::new(TailEvent::from_record(record)));
+        }
+    }
+
+    /// Events published from now on, for `endpoint` only if given, as SSE
+    /// `delivery` events.
+    pub fn subscribe(&self, endpoint: Option<String>) -> impl Stream<Item = Result<Event, Infallible>> {
+        futures_util::stream::unfold(self.0.subscribe(), move |mut receiver| {
+            let endpoint = endpoint.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) if endpoint.as_ref().is_none_or(|e| *e == event.endpoint) => {
+                            let sse = Event::default().event("delivery").json_data(&*event).unwrap_or_default();
+                            return Some((Ok(sse), receiver));
+                        }
+                        Ok(_) => continue,
+                        // A slow subscriber misses events rather than holding them up
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Read `delivery` events from an SSE response until the server closes it.
+pub async fn follow(mut response: reqwest::Response, mut on_event: impl FnMut(TailEvent)) -> Result<(), String> {
+    let mut buffer = String::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+        while let Some(end) = buffer.find("\n\n") {
+            let message: String = buffer.drain(..end + 2).collect();
+            let data: Vec<&str> = message
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect();
+            // Keep-alive comments carry no data
+            if data.is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&data.join("\n")) {
+                Ok(event) => on_event(event),
+                Err(e) => return Err(format!("Invalid tail event: {}", e)),
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delivery_log::{InboundRecord, TargetRecord};
+
+    fn record(endpoint: &str, rendered: &str) -> DeliveryRecord {
+        let inbound = InboundRecord {
+            method: "POST".to_string(),
+            source: "127.0.0.1".to_string(),
+            content_type: None,
+            body_bytes: 0,
+            headers: Default::default(),
+            body: None,
+        };
+        let mut record = DeliveryRecord::new("req-1", Utc::now(), endpoint, inbound);
+        record.target = Some(TargetRecord {
+            method: "POST".to_string(),
+            url: "https://chat.example.com/hooks".to_string(),
+        });
+        record.status = Some(200);
+        record.rendered_body = Some(rendered.to_string());
+        record.finish(None);
+        record
+    }
+
+    #[test]
+    fn test_event_from_record() {
+        let event = TailEvent::from_record(&record("/webhook/github", &"x".repeat(300)));
+        assert_eq!(event.route.as_deref(), Some("POST https://chat.example.com/hooks"));
+        assert_eq!(event.outcome, Outcome::Delivered);
+        assert_eq!(event.preview.unwrap().chars().count(), PREVIEW_CHARS + 1);
+    }
+}
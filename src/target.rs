@@ -0,0 +1,200 @@
+use crate::config::Target;
+use handlebars::Handlebars;
+use reqwest::{Method, Url};
+use serde_json::{Map, Value};
+
+const URL_TEMPLATE: &str = "url";
+const METHOD_TEMPLATE: &str = "method";
+
+/// Compiled templates for a register's target URL, method, query parameters
+/// and header values.
+///
+/// All of them are rendered with the same context as the body template.
+/// Values interpolated into the URL with `{{ }}` are percent-encoded so they
+/// cannot change the URL structure; `{{{ }}}` inserts them unescaped.
+#[derive(Debug)]
+pub struct TargetTemplates {
+    url: Handlebars<'static>,
+    text: Handlebars<'static>,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+}
+
+/// A target request with all templates rendered and the URL validated.
+#[derive(Debug, Clone)]
+pub struct RenderedTarget {
+    pub url: Url,
+    pub method: Method,
+    pub headers: Vec<(String, String)>,
+}
+
+impl TargetTemplates {
+    pub fn compile(target: &Target) -> Result<Self, String> {
+        let mut url = Handlebars::new();
+        url.register_escape_fn(encode_url_component);
+        url.register_template_string(URL_TEMPLATE, &target.url)
+            .map_err(|e| format!("target url template error: {}", e))?;
+
+        let mut text = Handlebars::new();
+        text.register_escape_fn(handlebars::no_escape);
+        text.register_template_string(METHOD_TEMPLATE, &target.method)
+            .map_err(|e| format!("target method template error: {}", e))?;
+
+        let mut query: Vec<(String, String)> = Vec::new();
+        for (name, value) in &target.query {
+            let template = format!("query:{}", name);
+            text.register_template_string(&template, value)
+                .map_err(|e| format!("target query '{}' template error: {}", name, e))?;
+            query.push((name.clone(), template));
+        }
+        query.sort();
+
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for (name, value) in &target.headers {
+            let template = format!("header:{}", name);
+            text.register_template_string(&template, value)
+                .map_err(|e| format!("target header '{}' template error: {}", name, e))?;
+            headers.push((name.clone(), template));
+        }
+        headers.sort();
+
+        Ok(Self {
+            url,
+            text,
+            query,
+            headers,
+        })
+    }
+
+    /// Render the target for one event. `url_override` replaces the URL
+    /// template with an already final URL, e.g. one chosen by a script.
+    pub fn render(&self, data: &Map<String, Value>, url_override: Option<&str>) -> Result<RenderedTarget, String> {
+        let raw_url = match url_override {
+            Some(url) => url.to_string(),
+            None => self
+                .url
+                .render(URL_TEMPLATE, data)
+                .map_err(|e| format!("Target URL rendering failed: {}", e))?,
+        };
+        let mut url = validate_url(raw_url.trim())?;
+
+        if !self.query.is_empty() {
+            let mut pairs = url.query_pairs_mut();
+            for (name, template) in &self.query {
+                let value = self
+                    .text
+                    .render(template, data)
+                    .map_err(|e| format!("Target query '{}' rendering failed: {}", name, e))?;
+                pairs.append_pair(name, &value);
+            }
+        }
+
+        let method = self
+            .text
+            .render(METHOD_TEMPLATE, data)
+            .map_err(|e| format!("Target method rendering failed: {}", e))?
+            .trim()
+            .to_uppercase();
+        let method = match method.as_str() {
+            "GET" => Method::GET,
+            "POST" => Method::POST,
+            "PUT" => Method::PUT,
+            "DELETE" => Method::DELETE,
+            "PATCH" => Method::PATCH,
+            _ => return Err(format!("Unsupported HTTP method: {}", method)),
+        };
+
+        let mut headers = Vec::with_capacity(self.headers.len());
+        for (name, template) in &self.headers {
+            let value = self
+                .text
+                .render(template, data)
+                .map_err(|e| format!("Target header '{}' rendering failed: {}", name, e))?;
+            headers.push((name.clone(), value));
+        }
+
+        Ok(RenderedTarget { url, method, headers })
+    }
+}
+
+/// Check that a rendered URL is an absolute http(s) URL with a host.
+pub fn validate_url(raw: &str) -> Result<Url, String> {
+    let url = Url::parse(raw).map_err(|e| format!("Invalid target URL '{}': {}", raw, e))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!("Invalid target URL '{}': scheme must be http or https", raw));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(format!("Invalid target URL '{}': missing host", raw));
+    }
+    Ok(url)
+}
+
+/// Percent-encode everything except RFC 3986 unreserved characters.
+fn encode_url_component(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn target(url: &str, method: &str) -> Target {
+        serde_json::from_value(json!({ "url": url, "method": method })).unwrap()
+    }
+
+    fn data(value: Value) -> Map<String, Value> {
+        crate::json_to_template_data(&value)
+    }
+
+    #[test]
+    fn test_render_url_encodes_values() {
+        let mut target = target("https://api.example.com/teams/{{labels.team}}/alerts", "{{verb}}");
+        target.query = HashMap::from([("q".to_string(), "{{labels.team}} & co".to_string())]);
+        target.headers = HashMap::from([("X-Priority".to_string(), "{{severity}}".to_string())]);
+
+        let templates = TargetTemplates::compile(&target).unwrap();
+        let rendered = templates
+            .render(
+                &data(json!({"labels": {"team": "a/b c"}, "severity": "P1 <high>", "verb": "put"})),
+                None,
+            )
+            .unwrap();
+
+        assert_eq!(
+            rendered.url.as_str(),
+            "https://api.example.com/teams/a%2Fb%20c/alerts?q=a%2Fb+c+%26+co"
+        );
+        assert_eq!(rendered.method, Method::PUT);
+        assert_eq!(rendered.headers, vec![("X-Priority".to_string(), "P1 <high>".to_string())]);
+    }
+
+    #[test]
+    fn test_render_rejects_invalid_urls() {
+        let templates = TargetTemplates::compile(&target("{{{base}}}/x", "POST")).unwrap();
+        assert!(templates.render(&data(json!({"base": "file:///etc"})), None).is_err());
+        assert!(templates.render(&data(json!({"base": ""})), None).is_err());
+        assert!(templates
+            .render(&data(json!({"base": "http://ok.example"})), None)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_render_url_override() {
+        let templates = TargetTemplates::compile(&target("http://a.example/{{x}}", "POST")).unwrap();
+        let rendered = templates
+            .render(&data(json!({})), Some("http://b.example/y"))
+            .unwrap();
+        assert_eq!(rendered.url.as_str(), "http://b.example/y");
+    }
+}
@@ -0,0 +1,253 @@
+use crate::config::{OAuth2Config, TargetAuth};
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Lifetime assumed for tokens whose response has no `expires_in`.
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(300);
+
+/// Applies target credentials to outbound requests, caching OAuth2 tokens.
+///
+/// Each token endpoint/client/scope combination has its own slot, so
+/// concurrent requests wait for a single token fetch instead of each
+/// fetching their own.
+#[derive(Debug)]
+pub struct TokenCache {
+    http_client: Client,
+    slots: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<CachedToken>>>>>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedToken {
+    access_token: String,
+    refresh_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+/// Why a target request failed.
+#[derive(Debug)]
+pub enum SendError {
+    /// The request never reached the target: its token could not be fetched
+    /// or no connection was made
+    NotSent(String),
+    /// The request may have reached the target
+    Failed(String),
+}
+
+impl From<reqwest::Error> for SendError {
+    fn from(e: reqwest::Error) -> Self {
+        match e.is_connect() || e.is_builder() {
+            true => Self::NotSent(e.to_string()),
+            false => Self::Failed(e.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotSent(e) | Self::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+impl TokenCache {
+    pub fn new(http_client: Client) -> Self {
+        Self {
+            http_client,
+            slots: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Attach the credentials for `auth` to a request.
+    pub async fn apply(&self, auth: &TargetAuth, request: RequestBuilder) -> Result<RequestBuilder, String> {
+        match auth {
+            TargetAuth::Basic { username, password } => Ok(request.basic_auth(username, Some(password))),
+            TargetAuth::Bearer { token } => Ok(request.bearer_auth(token)),
+            TargetAuth::Oauth2(config) => {
+                let token = self.token(config, false).await?;
+                Ok(request.bearer_auth(token))
+            }
+        }
+    }
+
+    /// Send a request with the credentials for `auth`, retrying once with a
+    /// freshly fetched token when an OAuth2 target answers 401.
+    pub async fn send(&self, auth: Option<&TargetAuth>, request: RequestBuilder) -> Result<Response, String> {
+        self.try_send(auth, request).await.map_err(|e| e.to_string())
+    }
+
+    /// Like [`TokenCache::send`], telling whether a failed request may have
+    /// reached the target.
+    pub async fn try_send(&self, auth: Option<&TargetAuth>, request: RequestBuilder) -> Result<Response, SendError> {
+        let Some(auth) = auth else {
+            return request.send().await.map_err(SendError::from);
+        };
+
+        let retry = request.try_clone();
+        let response = self
+            .apply(auth, request)
+            .await
+            .map_err(SendError::NotSent)?
+            .send()
+            .await?;
+
+        match (auth, retry) {
+            (TargetAuth::Oauth2(config), Some(retry)) if response.status() == StatusCode::UNAUTHORIZED => {
+                tracing::warn!(token_url = %config.token_url, "Target rejected OAuth2 token, refreshing and retrying");
+                let token = self.token(config, true).await.map_err(SendError::NotSent)?;
+                Ok(retry.bearer_auth(token).send().await?)
+            }
+            _ => Ok(response),
+        }
+    }
+
+    /// Return a valid access token, fetching a new one when the cached token
+    /// is about to expire or `force_refresh` is set (e.g. after a 401).
+    pub async fn token(&self, config: &OAuth2Config, force_refresh: bool) -> Result<String, String> {
+        let slot = {
+            let key = format!("{}|{}|{}", config.token_url, config.client_id, config.scopes.join(" "));
+            let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+            slots.entry(key).or_default().clone()
+        };
+
+        let mut cached = slot.lock().await;
+        if let Some(token) = cached.as_ref() {
+            if !force_refresh && Instant::now() < token.refresh_at {
+                return Ok(token.access_token.clone());
+            }
+        }
+
+        let token = self.fetch(config).await?;
+        let access_token = token.access_token.clone();
+        *cached = Some(token);
+        Ok(access_token)
+    }
+
+    async fn fetch(&self, config: &OAuth2Config) -> Result<CachedToken, String> {
+        let mut form = vec![
+            ("grant_type", "client_credentials".to_string()),
+            ("client_id", config.client_id.clone()),
+            ("client_secret", config.client_secret.clone()),
+        ];
+        if !config.scopes.is_empty() {
+            form.push(("scope", config.scopes.join(" ")));
+        }
+        if let Some(audience) = &config.audience {
+            form.push(("audience", audience.clone()));
+        }
+
+        let requested_at = Instant::now();
+        let response = self
+            .http_client
+            .post(&config.token_url)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| format!("OAuth2 token request failed: {}", e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!("OAuth2 token endpoint returned {}", status));
+        }
+
+        let token: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid OAuth2 token response: {}", e))?;
+
+        let lifetime = token
+            .expires_in
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+        let refresh_at = requested_at + lifetime.saturating_sub(Duration::from_secs(config.refresh_before_seconds));
+
+        tracing::debug!(
+            token_url = %config.token_url,
+            client_id = %config.client_id,
+            expires_in = lifetime.as_secs(),
+            "Fetched OAuth2 access token"
+        );
+
+        Ok(CachedToken {
+            access_token: token.access_token,
+            refresh_at,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Json, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    async fn token_server(expires_in: u64) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        let app = Router::new().route(
+            "/token",
+            post(move |body: String| {
+                let counter = counter.clone();
+                async move {
+                    assert!(body.contains("grant_type=client_credentials"));
+                    let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    Json(serde_json::json!({
+                        "access_token": format!("token-{}", n),
+                        "token_type": "Bearer",
+                        "expires_in": expires_in,
+                    }))
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}/token", addr), hits)
+    }
+
+    fn oauth2(token_url: String) -> OAuth2Config {
+        OAuth2Config {
+            token_url,
+            client_id: "client".to_string(),
+            client_secret: "secret".to_string(),
+            scopes: vec!["alerts.write".to_string()],
+            audience: None,
+            refresh_before_seconds: 60,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oauth2_token_is_cached() {
+        let (url, hits) = token_server(3600).await;
+        let cache = TokenCache::new(Client::new());
+        let config = oauth2(url);
+
+        assert_eq!(cache.token(&config, false).await.unwrap(), "token-1");
+        assert_eq!(cache.token(&config, false).await.unwrap(), "token-1");
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        // A forced refresh, as done after a 401, always fetches a new token
+        assert_eq!(cache.token(&config, true).await.unwrap(), "token-2");
+    }
+
+    #[tokio::test]
+    async fn test_oauth2_token_refreshed_before_expiry() {
+        // Tokens expiring within the refresh window are never reused
+        let (url, hits) = token_server(30).await;
+        let cache = TokenCache::new(Client::new());
+        let config = oauth2(url);
+
+        assert_eq!(cache.token(&config, false).await.unwrap(), "token-1");
+        assert_eq!(cache.token(&config, false).await.unwrap(), "token-2");
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+}
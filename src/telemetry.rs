@@ -0,0 +1,160 @@
+//! OpenTelemetry export and W3C trace context propagation.
+//!
+//! With an OTLP endpoint configured, `tracing` spans are exported over
+//! OTLP/HTTP (JSON) and the outbound target request carries the
+//! `traceparent` of its send span. Without one, an incoming `traceparent`
+//! and `tracestate` are forwarded to the target unchanged.
+
+use axum::http::HeaderMap;
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::Context;
+use opentelemetry_otlp::{Protocol, WithExportConfig};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use opentelemetry_sdk::Resource;
+use std::collections::HashMap;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const TRACE_HEADERS: [&str; 2] = ["traceparent", "tracestate"];
+
+/// Build a tracer provider exporting spans to an OTLP/HTTP collector.
+///
+/// `endpoint` is the collector's base URL; `/v1/traces` is appended unless
+/// the URL already has a path.
+pub fn tracer_provider(endpoint: &str, service_name: &str) -> Result<SdkTracerProvider, String> {
+    let mut url = reqwest::Url::parse(endpoint).map_err(|e| format!("Invalid OTLP endpoint '{}': {}", endpoint, e))?;
+    if url.path() == "/" {
+        url.set_path("/v1/traces");
+    }
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_protocol(Protocol::HttpJson)
+        .with_endpoint(url.as_str())
+        .build()
+        .map_err(|e| format!("Failed to create OTLP exporter: {}", e))?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(service_name.to_string()).build())
+        .build())
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+struct MapInjector<'a>(&'a mut HashMap<String, String>);
+
+impl Injector for MapInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        self.0.insert(key.to_string(), value);
+    }
+}
+
+/// The trace context of an incoming request, used as the parent of its
+/// receive span.
+pub fn extract_context(headers: &HeaderMap) -> Context {
+    TraceContextPropagator::new().extract(&HeaderExtractor(headers))
+}
+
+/// Trace headers for an outbound request made within `span`.
+pub fn trace_headers(span: &tracing::Span, inbound: &HeaderMap) -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    let context = span.context();
+    if context.span().span_context().is_valid() {
+        TraceContextPropagator::new().inject_context(&context, &mut MapInjector(&mut headers));
+    } else {
+        for name in TRACE_HEADERS {
+            if let Some(value) = inbound.get(name).and_then(|v| v.to_str().ok()) {
+                headers.insert(name.to_string(), value.to_string());
+            }
+        }
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Router};
+    use opentelemetry::trace::{Tracer, TracerProvider};
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn inbound() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("traceparent", TRACEPARENT.parse().unwrap());
+        headers.insert("tracestate", "vendor=abc".parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_trace_headers_pass_through_without_exporter() {
+        let headers = trace_headers(&tracing::Span::none(), &inbound());
+        assert_eq!(headers["traceparent"], TRACEPARENT);
+        assert_eq!(headers["tracestate"], "vendor=abc");
+    }
+
+    #[test]
+    fn test_trace_headers_continue_incoming_trace() {
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("webhook.send");
+            span.set_parent(extract_context(&inbound())).unwrap();
+            let headers = trace_headers(&span, &HeaderMap::new());
+
+            // Same trace, new parent span
+            let traceparent = &headers["traceparent"];
+            assert!(traceparent.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"), "{}", traceparent);
+            assert!(!traceparent.contains("00f067aa0ba902b7"));
+            assert_eq!(headers["tracestate"], "vendor=abc");
+        });
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_spans_exported_to_collector() {
+        let received = Arc::new(Mutex::new(Vec::<serde_json::Value>::new()));
+        let sink = received.clone();
+        let app = Router::new().route(
+            "/v1/traces",
+            post(move |body: axum::body::Bytes| {
+                let sink = sink.clone();
+                async move {
+                    sink.lock().unwrap().push(serde_json::from_slice(&body).unwrap());
+                    "{}"
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let provider = tracer_provider(&format!("http://{}", addr), "hermes-test").unwrap();
+        tokio::task::spawn_blocking(move || {
+            provider.tracer("test").in_span("webhook.receive", |_| {});
+            provider.force_flush().unwrap();
+            provider.shutdown().unwrap();
+        })
+        .await
+        .unwrap();
+
+        let exported = serde_json::to_string(&*received.lock().unwrap()).unwrap();
+        assert!(exported.contains("webhook.receive"), "{}", exported);
+        assert!(exported.contains("hermes-test"), "{}", exported);
+    }
+}
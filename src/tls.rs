@@ -0,0 +1,169 @@
+use crate::config::Args;
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+/// TLS settings for the inbound listener.
+#[derive(Debug, Clone)]
+pub struct ListenerTls {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+    pub client_ca: Option<PathBuf>,
+    pub client_auth_optional: bool,
+}
+
+impl ListenerTls {
+    pub fn from_args(args: &Args) -> Option<Self> {
+        match (&args.tls_cert, &args.tls_key) {
+            (Some(cert), Some(key)) => Some(Self {
+                cert: cert.clone(),
+                key: key.clone(),
+                client_ca: args.tls_client_ca.clone(),
+                client_auth_optional: args.tls_client_auth_optional,
+            }),
+            _ => None,
+        }
+    }
+
+    fn files(&self) -> Vec<&Path> {
+        let mut files = vec![self.cert.as_path(), self.key.as_path()];
+        files.extend(self.client_ca.as_deref());
+        files
+    }
+
+    /// Build a rustls server config from the certificate files, advertising
+    /// HTTP/2 and HTTP/1.1 over ALPN.
+    pub fn server_config(&self) -> Result<ServerConfig, String> {
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+
+        let certs = CertificateDer::pem_file_iter(&self.cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("Failed to read TLS certificate {}: {}", self.cert.display(), e))?;
+        if certs.is_empty() {
+            return Err(format!("TLS certificate {} contains no certificates", self.cert.display()));
+        }
+        let key = PrivateKeyDer::from_pem_file(&self.key)
+            .map_err(|e| format!("Failed to read TLS key {}: {}", self.key.display(), e))?;
+
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| format!("Invalid TLS configuration: {}", e))?;
+
+        let builder = match &self.client_ca {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in CertificateDer::pem_file_iter(path)
+                    .map_err(|e| format!("Failed to read client CA {}: {}", path.display(), e))?
+                {
+                    let cert = cert.map_err(|e| format!("Failed to read client CA {}: {}", path.display(), e))?;
+                    roots
+                        .add(cert)
+                        .map_err(|e| format!("Invalid client CA {}: {}", path.display(), e))?;
+                }
+
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = if self.client_auth_optional {
+                    verifier.allow_unauthenticated()
+                } else {
+                    verifier
+                };
+                let verifier = verifier
+                    .build()
+                    .map_err(|e| format!("Invalid client CA {}: {}", path.display(), e))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut config = builder
+            .with_single_cert(certs, key)
+            .map_err(|e| format!("Invalid TLS certificate or key: {}", e))?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+
+    /// Latest modification time of the certificate files.
+    fn modified(&self) -> Option<SystemTime> {
+        self.files()
+            .into_iter()
+            .filter_map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .max()
+    }
+
+    /// Poll the certificate files and reload the listener's TLS config when
+    /// they change. A failed reload keeps serving the previous certificate.
+    pub fn spawn_reloader(self, config: RustlsConfig, interval: Duration) {
+        tokio::spawn(async move {
+            let mut last_modified = self.modified();
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+
+            loop {
+                ticker.tick().await;
+                let modified = self.modified();
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+
+                match self.server_config() {
+                    Ok(server_config) => {
+                        config.reload_from_config(Arc::new(server_config));
+                        info!(cert = %self.cert.display(), "Reloaded TLS certificate");
+                    }
+                    Err(e) => warn!(error = %e, "Failed to reload TLS certificate, keeping the previous one"),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, contents: &str) -> PathBuf {
+        let path = dir.join(name);
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_server_config_with_client_ca() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let client_ca = rcgen::generate_simple_self_signed(vec!["Hermes Client CA".to_string()]).unwrap();
+
+        let tls = ListenerTls {
+            cert: write(dir.path(), "cert.pem", &server.cert.pem()),
+            key: write(dir.path(), "key.pem", &server.key_pair.serialize_pem()),
+            client_ca: Some(write(dir.path(), "ca.pem", &client_ca.cert.pem())),
+            client_auth_optional: false,
+        };
+
+        let config = tls.server_config().unwrap();
+        assert_eq!(config.alpn_protocols, vec![b"h2".to_vec(), b"http/1.1".to_vec()]);
+        assert!(tls.modified().is_some());
+    }
+
+    #[test]
+    fn test_server_config_reports_bad_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+
+        let tls = ListenerTls {
+            cert: write(dir.path(), "cert.pem", &server.cert.pem()),
+            key: write(dir.path(), "key.pem", "not a key"),
+            client_ca: None,
+            client_auth_optional: false,
+        };
+        let err = tls.server_config().unwrap_err();
+        assert!(err.contains("key.pem"), "{}", err);
+    }
+}
@@ -0,0 +1,222 @@
+//! WebAssembly transform plugins.
+//!
+//! A transform module is a plain core WebAssembly module with no imports. It
+//! must export:
+//!
+//! - `memory`: the module's linear memory
+//! - `hermes_alloc(len: i32) -> i32`: returns a pointer to `len` writable bytes
+//! - `hermes_transform(ptr: i32, len: i32) -> i64`: transforms the input found
+//!   at `ptr`/`len` and returns the output location packed as
+//!   `(out_ptr << 32) | out_len`
+//!
+//! The input is UTF-8 JSON of the form
+//! `{"request": {"endpoint", "method", "headers", "body"}, "payload": ...}`,
+//! where `body` is the raw inbound body and `payload` its decoded form.
+//! The output is UTF-8 JSON of the form
+//! `{"body": ..., "headers": {"Name": "value"}, "drop": false}`, where `body`
+//! is either a string sent as the rendered body or any other JSON value, which
+//! is serialized as JSON. `headers` and `drop` are optional.
+//!
+//! Every call runs in a fresh instance with a fuel budget and a memory cap.
+
+use crate::config::WasmConfig;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use wasmtime::{Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap};
+
+pub struct WasmTransform {
+    engine: Engine,
+    module: Module,
+    fuel: u64,
+    max_memory_bytes: usize,
+}
+
+/// Result of a single transform call.
+#[derive(Debug, Clone)]
+pub struct WasmOutput {
+    pub body: String,
+    pub headers: HashMap<String, String>,
+    pub drop: bool,
+}
+
+#[derive(Deserialize)]
+struct RawOutput {
+    #[serde(default)]
+    body: Value,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default)]
+    drop: bool,
+}
+
+struct StoreState {
+    limits: StoreLimits,
+}
+
+impl WasmTransform {
+    pub fn load(config: &WasmConfig) -> Result<Self, String> {
+        let bytes = std::fs::read(&config.module)
+            .map_err(|e| format!("failed to read wasm module {}: {}", config.module.display(), e))?;
+        Self::from_bytes(&bytes, config.fuel, config.max_memory_bytes)
+    }
+
+    /// Compile a module from its binary or text (WAT) representation.
+    pub fn from_bytes(bytes: &[u8], fuel: u64, max_memory_bytes: usize) -> Result<Self, String> {
+        let mut engine_config = wasmtime::Config::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config).map_err(|e| format!("wasm engine error: {:#}", e))?;
+        let module = Module::new(&engine, bytes).map_err(|e| format!("wasm compile error: {:#}", e))?;
+
+        for export in ["memory", "hermes_alloc", "hermes_transform"] {
+            if module.get_export(export).is_none() {
+                return Err(format!("wasm module does not export '{}'", export));
+            }
+        }
+
+        Ok(Self {
+            engine,
+            module,
+            fuel,
+            max_memory_bytes,
+        })
+    }
+
+    pub fn run(&self, request: &Value, payload: &Value) -> Result<WasmOutput, String> {
+        let input = serde_json::to_vec(&serde_json::json!({
+            "request": request,
+            "payload": payload,
+        }))
+        .map_err(|e| e.to_string())?;
+
+        let raw = self.call(&input).map_err(|e| {
+            if e.downcast_ref::<Trap>() == Some(&Trap::OutOfFuel) {
+                format!("wasm transform exceeded its fuel budget of {}", self.fuel)
+            } else {
+                format!("wasm transform failed: {:#}", e)
+            }
+        })?;
+
+        let output: RawOutput = serde_json::from_slice(&raw)
+            .map_err(|e| format!("wasm transform returned invalid output: {}", e))?;
+        let body = match output.body {
+            Value::String(body) => body,
+            other => other.to_string(),
+        };
+
+        Ok(WasmOutput {
+            body,
+            headers: output.headers,
+            drop: output.drop,
+        })
+    }
+
+    fn call(&self, input: &[u8]) -> wasmtime::Result<Vec<u8>> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.max_memory_bytes)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, StoreState { limits });
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.fuel)?;
+
+        let linker = Linker::new(&self.engine);
+        let instance = linker.instantiate(&mut store, &self.module)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| wasmtime::Error::msg("export 'memory' is not a memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "hermes_alloc")?;
+        let transform = instance.get_typed_func::<(i32, i32), i64>(&mut store, "hermes_transform")?;
+
+        let input_len = i32::try_from(input.len())?;
+        let input_ptr = alloc.call(&mut store, input_len)?;
+        memory.write(&mut store, input_ptr as u32 as usize, input)?;
+
+        let packed = transform.call(&mut store, (input_ptr, input_len))? as u64;
+        let out_ptr = (packed >> 32) as usize;
+        let out_len = (packed & 0xffff_ffff) as usize;
+
+        // Bounds-checked against the guest's memory before anything is
+        // allocated for the output
+        let output = out_ptr
+            .checked_add(out_len)
+            .and_then(|end| memory.data(&store).get(out_ptr..end))
+            .ok_or_else(|| {
+                wasmtime::Error::msg(format!("output range {}+{} is outside the module's memory", out_ptr, out_len))
+            })?;
+        Ok(output.to_vec())
+    }
+}
+
+impl std::fmt::Debug for WasmTransform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WasmTransform")
+            .field("fuel", &self.fuel)
+            .field("max_memory_bytes", &self.max_memory_bytes)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const ECHO_OUTPUT: &str = r#"{"body":{"ok":true},"headers":{"X-Plugin":"echo"}}"#;
+
+    fn constant_module(output: &str) -> String {
+        format!(
+            r#"(module
+                (memory (export "memory") 1)
+                (data (i32.const 0) "{output}")
+                (func (export "hermes_alloc") (param i32) (result i32) i32.const 1024)
+                (func (export "hermes_transform") (param i32 i32) (result i64)
+                    i64.const {len}))"#,
+            output = output.replace('"', "\\\""),
+            len = output.len(),
+        )
+    }
+
+    #[test]
+    fn test_wasm_transform_output() {
+        let module = constant_module(ECHO_OUTPUT);
+        let transform = WasmTransform::from_bytes(module.as_bytes(), 100_000, 1 << 20).unwrap();
+        let output = transform.run(&json!({}), &json!({"a": 1})).unwrap();
+
+        assert_eq!(output.body, r#"{"ok":true}"#);
+        assert_eq!(output.headers.get("X-Plugin").unwrap(), "echo");
+        assert!(!output.drop);
+    }
+
+    #[test]
+    fn test_wasm_transform_fuel_limit() {
+        let module = r#"(module
+            (memory (export "memory") 1)
+            (func (export "hermes_alloc") (param i32) (result i32) i32.const 0)
+            (func (export "hermes_transform") (param i32 i32) (result i64)
+                (loop br 0)
+                i64.const 0))"#;
+        let transform = WasmTransform::from_bytes(module.as_bytes(), 10_000, 1 << 20).unwrap();
+        let err = transform.run(&json!({}), &json!({})).unwrap_err();
+        assert!(err.contains("fuel"), "{}", err);
+    }
+
+    #[test]
+    fn test_wasm_transform_output_out_of_bounds() {
+        // out_ptr 0, out_len 0xffff_ffff: far beyond the single 64 KiB page
+        let module = r#"(module
+            (memory (export "memory") 1)
+            (func (export "hermes_alloc") (param i32) (result i32) i32.const 0)
+            (func (export "hermes_transform") (param i32 i32) (result i64)
+                i64.const 0xffffffff))"#;
+        let transform = WasmTransform::from_bytes(module.as_bytes(), 100_000, 1 << 20).unwrap();
+        let err = transform.run(&json!({}), &json!({})).unwrap_err();
+        assert!(err.contains("outside the module's memory"), "{}", err);
+    }
+
+    #[test]
+    fn test_wasm_transform_missing_export() {
+        let err = WasmTransform::from_bytes(b"(module (memory (export \"memory\") 1))", 1, 1 << 20).unwrap_err();
+        assert!(err.contains("hermes_alloc"));
+    }
+}